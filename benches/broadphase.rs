@@ -0,0 +1,86 @@
+// Compares the spatial hash broadphase used by `collision_detection` against checking every pair.
+// Run with `cargo bench --bench broadphase`.
+
+#[path = "../src/spatial_hash.rs"]
+mod spatial_hash;
+
+use std::time::{Duration, Instant};
+
+use bevy::math::Vec2;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use spatial_hash::SpatialHash;
+
+const COLLIDER_COUNTS: [usize; 6] = [100, 500, 1_000, 2_500, 5_000, 10_000];
+// Colliders per square pixel, about what a busy 1280x720 window looks like
+const DENSITY: f32 = 50.0 / (1280.0 * 720.0);
+const ITERATIONS: u32 = 20;
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(0);
+
+    println!("{:>9} {:>14} {:>14} {:>8}", "colliders", "all pairs", "spatial hash", "pairs");
+    for count in COLLIDER_COUNTS {
+        let side = (count as f32 / DENSITY).sqrt();
+        // Same mix of radii as the game: asteroids of every size plus some bullets
+        let colliders: Vec<(Vec2, f32)> = (0..count)
+            .map(|_| {
+                let position = Vec2::new(rng.gen_range(0.0..side), rng.gen_range(0.0..side));
+                let radius = if rng.gen_bool(0.25) { 5.0 } else { 16.0 * rng.gen_range(1..=4) as f32 };
+                (position, radius)
+            })
+            .collect();
+
+        let mut brute_force_pairs = Vec::new();
+        let brute_force = time(|| {
+            brute_force_pairs = all_pairs(&colliders);
+        });
+
+        let mut broadphase = SpatialHash::default();
+        let mut spatial_hash_pairs = Vec::new();
+        let spatial_hash = time(|| {
+            spatial_hash_pairs = hashed_pairs(&mut broadphase, &colliders);
+        });
+
+        assert_eq!(brute_force_pairs, spatial_hash_pairs, "broadphase missed or invented a collision");
+        println!(
+            "{:>9} {:>12.3}ms {:>12.3}ms {:>8}",
+            count,
+            brute_force.as_secs_f64() * 1000.0,
+            spatial_hash.as_secs_f64() * 1000.0,
+            spatial_hash_pairs.len(),
+        );
+    }
+}
+
+// Average time of one run of `f`
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn all_pairs(colliders: &[(Vec2, f32)]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for (a, &(position_a, radius_a)) in colliders.iter().enumerate() {
+        for (b, &(position_b, radius_b)) in colliders.iter().enumerate().skip(a + 1) {
+            if position_a.distance(position_b) < radius_a + radius_b {
+                pairs.push((a, b));
+            }
+        }
+    }
+    pairs
+}
+
+fn hashed_pairs(broadphase: &mut SpatialHash, colliders: &[(Vec2, f32)]) -> Vec<(usize, usize)> {
+    broadphase.clear();
+    for (index, &(position, radius)) in colliders.iter().enumerate() {
+        broadphase.insert(index, position, radius);
+    }
+    broadphase
+        .candidate_pairs()
+        .into_iter()
+        .filter(|&(a, b)| colliders[a].0.distance(colliders[b].0) < colliders[a].1 + colliders[b].1)
+        .collect()
+}
@@ -0,0 +1,84 @@
+use bevy::{prelude::*, input::InputSystem, window::PrimaryWindow};
+
+use crate::{
+    MainCamera,
+    state::{AppState, PauseState},
+    asset_loader::SceneAssets,
+    despawn::InGameScoped,
+    input::ControlScheme,
+    settings::Settings,
+};
+
+// Draws over everything else in the play field
+const CROSSHAIR_Z: f32 = 10.0;
+const CROSSHAIR_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.8);
+
+pub struct AimPlugin;
+
+impl Plugin for AimPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AimTarget>()
+            .add_systems(PreUpdate, update_aim_target.after(InputSystem))
+            .add_systems(OnEnter(AppState::InGame), spawn_crosshair)
+            .add_systems(Update, update_crosshair.run_if(in_state(AppState::InGame).and_then(in_state(PauseState::Running))))
+            // A lost run stays on screen behind the game over summary, without the crosshair
+            .add_systems(OnExit(AppState::InGame), hide_crosshair);
+    }
+}
+
+// Where the mouse cursor is in the world, if it's over the window
+#[derive(Resource, Debug, Default)]
+pub struct AimTarget(pub Option<Vec2>);
+
+#[derive(Component, Debug)]
+struct Crosshair;
+
+fn update_aim_target(
+    mut aim_target: ResMut<AimTarget>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    let (Ok(window), Ok((camera, camera_transform))) = (window_query.get_single(), camera_query.get_single()) else {
+        aim_target.0 = None;
+        return;
+    };
+
+    aim_target.0 = window.cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor));
+}
+
+fn spawn_crosshair(mut commands: Commands, scene_assets: Res<SceneAssets>) {
+    commands.spawn((SpriteBundle {
+        texture: scene_assets.crosshair.clone(),
+        sprite: Sprite {
+            color: CROSSHAIR_COLOR,
+            ..default()
+        },
+        transform: Transform::from_xyz(0.0, 0.0, CROSSHAIR_Z),
+        visibility: Visibility::Hidden,
+        ..default()
+    }, Crosshair, InGameScoped));
+}
+
+// The crosshair follows the cursor, and only shows while aiming with the mouse
+fn update_crosshair(
+    mut crosshair_query: Query<(&mut Transform, &mut Visibility), With<Crosshair>>,
+    aim_target: Res<AimTarget>,
+    settings: Res<Settings>,
+) {
+    for (mut transform, mut visibility) in crosshair_query.iter_mut() {
+        match aim_target.0 {
+            Some(target) if settings.control_scheme == ControlScheme::MouseAim => {
+                transform.translation = target.extend(CROSSHAIR_Z);
+                *visibility = Visibility::Inherited;
+            }
+            _ => *visibility = Visibility::Hidden,
+        }
+    }
+}
+
+fn hide_crosshair(mut crosshair_query: Query<&mut Visibility, With<Crosshair>>) {
+    for mut visibility in crosshair_query.iter_mut() {
+        *visibility = Visibility::Hidden;
+    }
+}
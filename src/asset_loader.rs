@@ -6,6 +6,8 @@ pub struct SceneAssets {
     pub spaceship: Handle<Image>,
     pub bullet: Handle<Image>,
     pub lives: Handle<Image>,
+    pub shield: Handle<Image>,
+    pub crosshair: Handle<Image>,
     pub asteroids: [Handle<Image>; 4],
     pub font: Handle<Font>,
 }
@@ -24,6 +26,8 @@ fn load_assets(mut scene_assets: ResMut<SceneAssets>, asset_server: Res<AssetSer
         spaceship: asset_server.load("sprites/Player.png"),
         bullet: asset_server.load("sprites/Square.png"),
         lives: asset_server.load("sprites/Lives.png"),
+        shield: asset_server.load("sprites/Shield.png"),
+        crosshair: asset_server.load("sprites/Crosshair.png"),
         asteroids: [
             asset_server.load("sprites/Asteroid_01.png"), 
             asset_server.load("sprites/Asteroid_02.png"), 
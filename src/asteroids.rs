@@ -1,90 +1,353 @@
-use std::ops::Range;
+use std::ops::{Range, RangeInclusive};
 
 use bevy::{prelude::*, window::PrimaryWindow};
 use rand::Rng;
 
-use crate::{movement::{MovingObjectBundle, Acceleration, Velocity}, collisions::Collider, asset_loader::SceneAssets, state::AppState};
+use crate::{movement::{MovingObjectBundle, Acceleration, Velocity, Wraps, play_field_size, clear_position}, collisions::{Collider, CollisionLayers}, asset_loader::SceneAssets, state::AppState, schedules::{InGameSet, DamageSet}, despawn::{InGameScoped, reset_resource}, player::{Player, SpawnProtection, SPAWN_PROTECTION_RADIUS}, menu::TEXT_COLOR, health::{Health, ContactDamage, Destroyed}, rng::{GameRng, RngStream}};
 
 pub struct AsteroidPlugin;
 
 impl Plugin for AsteroidPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<AsteroidSpawnTimer>()
-        .add_systems(Update, (
-            spawn_asteroids,
-            tick_asteroid_spawn_timer,
-        ).run_if(in_state(AppState::InGame)));
+        app.init_resource::<Wave>()
+        .init_resource::<AsteroidSpawnConfig>()
+        .add_systems(OnEnter(AppState::InGame), reset_resource::<Wave>)
+        .add_systems(FixedUpdate, (
+            update_wave,
+            update_spawn_warnings,
+        ).run_if(in_state(AppState::InGame)).in_set(InGameSet::EntityUpdates))
+        .add_systems(FixedUpdate, (
+            destroy_asteroids,
+        ).run_if(in_state(AppState::InGame)).in_set(DamageSet::React));
     }
 }
 
-pub const ASTEROID_SPAWNRATE: f32 = 1.0;
 const ASTEROID_BASE_SIZE: f32 = 16.0;
 const ASTEROID_SPEED_RANGE: Range<f32> = 100.0..500.0;
+// How long the "Wave N" banner shows before the wave's asteroids arrive
+const WAVE_BANNER_TIME: f32 = 2.0;
+// Each wave is this much faster than the one before, up to WAVE_MAX_SPEED_SCALE
+const WAVE_SPEED_STEP: f32 = 0.08;
+const WAVE_MAX_SPEED_SCALE: f32 = 2.0;
+const WAVE_MAX_LARGE_ASTEROIDS: u32 = 8;
+const SPAWN_WARNING_COLOR: Color = Color::rgba(1.0, 0.2, 0.2, 0.5);
+// How many times a second the spawn warning blinks
+const SPAWN_WARNING_BLINK_RATE: f32 = 6.0;
+// How many smaller pieces an asteroid breaks into when shot
+const ASTEROID_FRAGMENT_COUNT: RangeInclusive<usize> = 2..=3;
+// Extra speed added on top of the parent's velocity, in a random direction, for every fragment
+const ASTEROID_FRAGMENT_SPREAD: Range<f32> = 50.0..150.0;
+// Score for destroying an asteroid, indexed by size. Smaller asteroids are harder to hit.
+const ASTEROID_SCORES: [u32; 4] = [100, 75, 50, 20];
+// Hits it takes to destroy an asteroid, indexed by size
+const ASTEROID_HEALTH: [u32; 4] = [1, 1, 2, 3];
+// Damage an asteroid does to a ship it hits, indexed by size
+const ASTEROID_CONTACT_DAMAGE: [u32; 4] = [1, 1, 2, 3];
+// From this wave on, some of the large asteroids are armoured
+const ARMOURED_FIRST_WAVE: u32 = 3;
+const ARMOURED_CHANCE: f64 = 0.2;
+// Armoured asteroids take this many times the hits, and are worth this many times the score
+const ARMOURED_MULTIPLIER: u32 = 3;
+const ARMOURED_COLOR: Color = Color::rgb(0.6, 0.65, 0.8);
 
+#[derive(Component, Debug)]
+pub struct Asteroid {
+    // Index into the asteroid sprites, 0 is the smallest and 3 the largest
+    pub size: usize,
+    // Armoured asteroids take more hits. Their fragments aren't armoured.
+    pub armoured: bool,
+}
+
+impl Asteroid {
+    pub fn new(size: usize) -> Self {
+        Self { size, armoured: false }
+    }
+
+    pub fn armoured(size: usize) -> Self {
+        Self { size, armoured: true }
+    }
+
+    pub fn score(&self) -> u32 {
+        ASTEROID_SCORES[self.size] * self.multiplier()
+    }
+
+    fn health(&self) -> u32 {
+        ASTEROID_HEALTH[self.size] * self.multiplier()
+    }
+
+    fn multiplier(&self) -> u32 {
+        if self.armoured { ARMOURED_MULTIPLIER } else { 1 }
+    }
+}
+
+// The current wave. Waves are numbered from 1, 0 means the run hasn't started yet.
+#[derive(Resource, Debug, Default)]
+pub struct Wave {
+    pub number: u32,
+    // Counts down the banner before the wave's asteroids are spawned. None while the wave is
+    // being played.
+    pub banner: Option<Timer>,
+}
+
+impl Wave {
+    // Sizes of the asteroids a wave starts with. Every wave has a few more large asteroids and,
+    // later on, medium and small ones mixed in.
+    fn asteroid_sizes(&self) -> Vec<usize> {
+        let mut sizes = Vec::new();
+        sizes.extend(std::iter::repeat_n(3, (self.number + 1).min(WAVE_MAX_LARGE_ASTEROIDS) as usize));
+        sizes.extend(std::iter::repeat_n(2, (self.number / 2) as usize));
+        sizes.extend(std::iter::repeat_n(1, (self.number / 3) as usize));
+        sizes
+    }
+
+    fn speed_scale(&self) -> f32 {
+        (1.0 + WAVE_SPEED_STEP * self.number.saturating_sub(1) as f32).min(WAVE_MAX_SPEED_SCALE)
+    }
+}
+
+// Where and when new asteroids are allowed to appear
+#[derive(Resource, Debug)]
+pub struct AsteroidSpawnConfig {
+    // Asteroids never appear closer than this to a player
+    pub min_player_distance: f32,
+    // How long a warning marker blinks on the spawn point before the asteroid arrives
+    pub warning_time: f32,
+}
+
+impl Default for AsteroidSpawnConfig {
+    fn default() -> Self {
+        Self {
+            min_player_distance: 200.0,
+            warning_time: 1.0,
+        }
+    }
+}
+
+// Text announcing the next wave
 #[derive(Component)]
-pub struct Asteroid;
+struct WaveBanner;
 
-fn spawn_asteroids(
+// Blinks where an asteroid is about to appear, then turns into that asteroid
+#[derive(Component)]
+struct SpawnWarning {
+    timer: Timer,
+    size: usize,
+    armoured: bool,
+    velocity: Vec3,
+}
+
+// Starts the next wave once the field is clear, with a banner first
+fn update_wave(
     mut commands: Commands,
+    mut wave: ResMut<Wave>,
+    asteroid_query: Query<(), Or<(With<Asteroid>, With<SpawnWarning>)>>,
+    banner_query: Query<Entity, With<WaveBanner>>,
+    mut player_query: Query<(&mut Player, &Transform, Has<SpawnProtection>)>,
     window_query: Query<&Window, With<PrimaryWindow>>,
     scene_assets: Res<SceneAssets>,
-    asteroids_spawn_timer: Res<AsteroidSpawnTimer>,
+    spawn_config: Res<AsteroidSpawnConfig>,
+    mut rng: ResMut<GameRng>,
+    time: Res<Time>,
 ) {
-    let window = window_query.get_single().unwrap();
-    if !asteroids_spawn_timer.timer.finished() {
+    let Some(banner) = &mut wave.banner else {
+        if !asteroid_query.is_empty() {
+            return;
+        }
+        // Field is clear, announce the next wave
+        wave.number += 1;
+        wave.banner = Some(Timer::from_seconds(WAVE_BANNER_TIME, TimerMode::Once));
+        for (mut player, _, _) in player_query.iter_mut() {
+            player.player_data.stats.level = wave.number;
+        }
+        spawn_wave_banner(&mut commands, &scene_assets, wave.number);
+        return;
+    };
+
+    if !banner.tick(time.delta()).just_finished() {
         return;
     }
-    let mut rng = rand::thread_rng();
-    // Pick random coords
-    let translation = Vec3::new(rng.gen_range(0.0..window.width()), rng.gen_range(0.0..window.height()), 0.0);
-    // let translation = Vec3::new(640.0, 360.0, 0.0);
+    wave.banner = None;
+    for entity in banner_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
 
-    // Pick Random Size
-    let asteroid_size = rng.gen_range(0..4);
+    let window = window_query.get_single().unwrap();
+    let field = play_field_size(window);
+    let rng = rng.stream(RngStream::Spawns);
+    let speed_scale = wave.speed_scale();
+    // Ships that just (re)spawned get a wider berth
+    let players: Vec<(Vec2, f32)> = player_query.iter()
+        .map(|(_, transform, protected)| {
+            let distance = if protected {
+                spawn_config.min_player_distance.max(SPAWN_PROTECTION_RADIUS)
+            } else {
+                spawn_config.min_player_distance
+            };
+            (transform.translation.xy(), distance)
+        })
+        .collect();
 
-    let asteroid = SpriteBundle {
-        transform: Transform::from_translation(translation).with_scale(Vec3::new(
-            0.25 * (asteroid_size + 1) as f32, 
-            0.25 * (asteroid_size + 1) as f32, 
-            0.25 * (asteroid_size + 1) as f32
-        )),
+    for asteroid_size in wave.asteroid_sizes() {
+        let translation = safe_spawn_position(rng, field, &players);
+        // Pick Random Speed
+        let velocity = random_direction(rng) * rng.gen_range(ASTEROID_SPEED_RANGE) * speed_scale;
+        let armoured = asteroid_size == 3 && wave.number >= ARMOURED_FIRST_WAVE && rng.gen_bool(ARMOURED_CHANCE);
+
+        commands.spawn((SpriteBundle {
+            transform: Transform::from_translation(translation).with_scale(asteroid_scale(asteroid_size)),
+            texture: scene_assets.asteroids[asteroid_size].clone(),
+            sprite: Sprite {
+                color: SPAWN_WARNING_COLOR,
+                ..default()
+            },
+            ..default()
+        }, SpawnWarning {
+            timer: Timer::from_seconds(spawn_config.warning_time, TimerMode::Once),
+            size: asteroid_size,
+            armoured,
+            velocity,
+        }, Wraps, InGameScoped));
+    }
+}
+
+// Picks a point on the edge of the play field that is at least the given distance from every
+// player, as far as that's possible
+fn safe_spawn_position(rng: &mut impl Rng, field: Vec2, players: &[(Vec2, f32)]) -> Vec3 {
+    clear_position(rng, field, players, |rng| match rng.gen_range(0..4) {
+        0 => Vec2::new(rng.gen_range(0.0..field.x), 0.0),
+        1 => Vec2::new(rng.gen_range(0.0..field.x), field.y),
+        2 => Vec2::new(0.0, rng.gen_range(0.0..field.y)),
+        _ => Vec2::new(field.x, rng.gen_range(0.0..field.y)),
+    }).extend(0.0)
+}
+
+// Blinks the spawn warnings and swaps them for asteroids once they run out
+fn update_spawn_warnings(
+    mut commands: Commands,
+    mut warning_query: Query<(Entity, &mut SpawnWarning, &mut Sprite, &Transform)>,
+    scene_assets: Res<SceneAssets>,
+    time: Res<Time>,
+) {
+    for (entity, mut warning, mut sprite, transform) in warning_query.iter_mut() {
+        if warning.timer.tick(time.delta()).just_finished() {
+            commands.entity(entity).despawn_recursive();
+            let asteroid = if warning.armoured { Asteroid::armoured(warning.size) } else { Asteroid::new(warning.size) };
+            spawn_asteroid(&mut commands, &scene_assets, asteroid, transform.translation, warning.velocity);
+            continue;
+        }
+
+        let blink_on = ((warning.timer.elapsed_secs() * SPAWN_WARNING_BLINK_RATE) as u32).is_multiple_of(2);
+        sprite.color.set_a(if blink_on { SPAWN_WARNING_COLOR.a() } else { 0.1 });
+    }
+}
+
+fn spawn_wave_banner(commands: &mut Commands, scene_assets: &SceneAssets, number: u32) {
+    commands.spawn((NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        ..default()
+    }, WaveBanner, InGameScoped)).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            format!("Wave {}", number),
+            TextStyle {
+                font: scene_assets.font.clone(),
+                font_size: 64.0,
+                color: TEXT_COLOR,
+            },
+        ));
+    });
+}
+
+pub fn spawn_asteroid(
+    commands: &mut Commands,
+    scene_assets: &SceneAssets,
+    asteroid: Asteroid,
+    translation: Vec3,
+    velocity: Vec3,
+) {
+    let asteroid_size = asteroid.size;
+    let sprite = SpriteBundle {
+        transform: Transform::from_translation(translation).with_scale(asteroid_scale(asteroid_size)),
         texture: scene_assets.asteroids[asteroid_size].clone(),
+        sprite: Sprite {
+            color: if asteroid.armoured { ARMOURED_COLOR } else { Color::WHITE },
+            ..default()
+        },
         ..default()
     };
 
-    // Pick Random Speed
-    let velocity = Vec3::new(
-        rng.gen_range(-1.0..1.0), 
-        rng.gen_range(-1.0..1.0),
-        0.0, 
-    ).normalize_or_zero() * rng.gen_range(ASTEROID_SPEED_RANGE);
-    // let velocity = Vec3::ZERO;
-
     commands.spawn((MovingObjectBundle {
         acceleration: Acceleration::new(Vec3::ZERO),
         velocity: Velocity::new(velocity),
-        collider: Collider::new(ASTEROID_BASE_SIZE * (asteroid_size + 1) as f32),
-        sprite: asteroid,
-    }, Asteroid, ));
-    println!("Spawned Asteroid with size {} at {} with speed {}", asteroid_size, translation, velocity); 
-
+        collider: Collider::new(
+            ASTEROID_BASE_SIZE * (asteroid_size + 1) as f32,
+            CollisionLayers::ASTEROID,
+            CollisionLayers::PLAYER | CollisionLayers::PLAYER_BULLET | CollisionLayers::ENEMY | CollisionLayers::ENEMY_BULLET,
+        ),
+        sprite,
+    }, Health::new(asteroid.health()), ContactDamage::new(ASTEROID_CONTACT_DAMAGE[asteroid_size]), asteroid, Wraps, InGameScoped, ));
 }
 
+// Breaks a destroyed asteroid into two or three pieces one size smaller. The smallest asteroids
+// just disappear.
+fn split_asteroid(
+    commands: &mut Commands,
+    scene_assets: &SceneAssets,
+    rng: &mut impl Rng,
+    asteroid: &Asteroid,
+    translation: Vec3,
+    velocity: Vec3,
+) {
+    if asteroid.size == 0 {
+        return;
+    }
+    let fragment_size = asteroid.size - 1;
 
-// Asteroid Timer
-#[derive(Resource)]
-pub struct AsteroidSpawnTimer {
-    pub timer: Timer,
+    for _ in 0..rng.gen_range(ASTEROID_FRAGMENT_COUNT) {
+        let direction = random_direction(rng);
+        // Start the pieces a little apart so they don't all sit on the same spot
+        let offset = direction * ASTEROID_BASE_SIZE * (fragment_size + 1) as f32 * 0.5;
+        let fragment_velocity = velocity + direction * rng.gen_range(ASTEROID_FRAGMENT_SPREAD);
+
+        spawn_asteroid(commands, scene_assets, Asteroid::new(fragment_size), translation + offset, fragment_velocity);
+    }
 }
 
-impl Default for AsteroidSpawnTimer {
-    fn default() -> Self {
-        AsteroidSpawnTimer {
-            timer: Timer::from_seconds(ASTEROID_SPAWNRATE, TimerMode::Repeating),
+// Asteroids shot to pieces break apart, while a ship ramming one smashes it completely so no
+// fragments end up right on top of the ship
+fn destroy_asteroids(
+    mut commands: Commands,
+    mut destroyed_events: EventReader<Destroyed>,
+    asteroid_query: Query<(&Asteroid, &Transform, &Velocity)>,
+    ship_query: Query<(), With<Player>>,
+    scene_assets: Res<SceneAssets>,
+    mut rng: ResMut<GameRng>,
+) {
+    for destroyed in destroyed_events.read() {
+        let Ok((asteroid, transform, velocity)) = asteroid_query.get(destroyed.entity) else {
+            continue;
+        };
+        if !ship_query.contains(destroyed.source) {
+            split_asteroid(&mut commands, &scene_assets, rng.stream(RngStream::Fragments), asteroid, transform.translation, velocity.value);
         }
+        commands.entity(destroyed.entity).despawn_recursive();
     }
 }
 
-fn tick_asteroid_spawn_timer(mut bullet_firerate_timer: ResMut<AsteroidSpawnTimer>, time: Res<Time>) {
-    bullet_firerate_timer.timer.tick(time.delta());
+fn asteroid_scale(asteroid_size: usize) -> Vec3 {
+    Vec3::splat(0.25 * (asteroid_size + 1) as f32)
+}
+
+fn random_direction(rng: &mut impl Rng) -> Vec3 {
+    Vec3::new(
+        rng.gen_range(-1.0..1.0), 
+        rng.gen_range(-1.0..1.0),
+        0.0, 
+    ).normalize_or_zero()
 }
@@ -1,56 +1,84 @@
 use bevy::{prelude::*, window::PrimaryWindow};
 
-use crate::{state::AppState, schedules::InGameSet};
-
-pub const BULLET_SPEED: f32 = 250.0;
-pub const BULLET_DESPAWN_DISTANCE: f32 = 50.0;
+use crate::{state::AppState, schedules::InGameSet, movement::{Velocity, Wraps, play_field_size}};
 
 pub struct BulletsPlugin;
 
 impl Plugin for BulletsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (
+        app.add_systems(FixedUpdate, (
             update_bullets.in_set(InGameSet::EntityUpdates),
             despawn_bullets.in_set(InGameSet::DespawnEntities),
-        ).run_if(in_state(AppState::InGame)).chain());
+        ).run_if(in_state(AppState::InGame)));
     }
 }
 
 // Bullet Stuff
-#[derive(Component)]
+// How long a bullet lives is up to its `Lifetime`, this covers everything else about it
+#[derive(Component, Debug)]
 pub struct Bullet {
-    pub direction: Vec2,
+    pub damage: u32,
+    // How many more targets it passes through before it is used up
+    pub pierce: u32,
+    // Targets it has hit so far
+    pub hits: u32,
+    // How many more times it bounces off the edges of the play field. Bullets that bounce don't wrap.
+    pub bounces: u32,
+    // Despawned once it has travelled this far
+    pub range: Option<f32>,
+    pub travelled: f32,
 }
 
-fn update_bullets(mut bullet_query: Query<(&mut Transform, &Bullet)>, time: Res<Time>) {
-    for (mut transform, _bullet) in bullet_query.iter_mut() {
-        let direction = Vec3::new(1.0, 1.0, 0.0);
-        transform.translation += direction * BULLET_SPEED * time.delta_seconds();
+impl Bullet {
+    pub fn new(damage: u32, pierce: u32, bounces: u32, range: Option<f32>) -> Self {
+        Self {
+            damage,
+            pierce,
+            hits: 0,
+            bounces,
+            range,
+            travelled: 0.0,
+        }
     }
 }
 
-fn despawn_bullets(mut commands: Commands, bullet_query: Query<((Entity, With<Bullet>), &Transform)>, window_query: Query<&Window, With<PrimaryWindow>>) {
+fn update_bullets(
+    mut bullet_query: Query<(&mut Transform, &mut Velocity, &mut Bullet), Without<Wraps>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
     let window = window_query.get_single().unwrap();
-    let x_min = 0.0 - BULLET_DESPAWN_DISTANCE;
-    let x_max = window.width() + BULLET_DESPAWN_DISTANCE;
-    let y_min = 0.0 - BULLET_DESPAWN_DISTANCE;
-    let y_max = window.height() + BULLET_DESPAWN_DISTANCE;
-
-    for (bullet, transform) in bullet_query.iter() {    
-        if transform.translation.x < x_min {
-            println!("Despawned at {}", transform.translation.xy());
-            commands.entity(bullet.0).despawn_recursive();
-        } else if transform.translation.x > x_max {
-            println!("Despawned at {}", transform.translation.xy());
-            commands.entity(bullet.0).despawn_recursive();
+    let field = play_field_size(window);
+
+    for (mut transform, mut velocity, mut bullet) in bullet_query.iter_mut() {
+        if bullet.bounces == 0 {
+            continue;
+        }
+        // Mirror the bullet back into the play field off whichever edge it crossed
+        let mut bounced = false;
+        for axis in 0..2 {
+            if transform.translation[axis] < 0.0 {
+                transform.translation[axis] = -transform.translation[axis];
+                velocity.value[axis] = velocity.value[axis].abs();
+                bounced = true;
+            } else if transform.translation[axis] > field[axis] {
+                transform.translation[axis] = 2.0 * field[axis] - transform.translation[axis];
+                velocity.value[axis] = -velocity.value[axis].abs();
+                bounced = true;
+            }
         }
-        if transform.translation.y < y_min {
-            println!("Despawned at {}", transform.translation.xy());
-            commands.entity(bullet.0).despawn_recursive();
-        } else if transform.translation.y > y_max {
-            println!("Despawned at {}", transform.translation.xy());
-            commands.entity(bullet.0).despawn_recursive();
+        if bounced {
+            bullet.bounces -= 1;
+            transform.rotation = Quat::from_rotation_arc_2d(Vec2::Y, velocity.value.xy().normalize_or_zero());
         }
     }
 }
 
+// Bullets that have gone as far as they can
+fn despawn_bullets(mut commands: Commands, mut bullet_query: Query<(Entity, &Velocity, &mut Bullet)>, time: Res<Time>) {
+    for (entity, velocity, mut bullet) in bullet_query.iter_mut() {
+        bullet.travelled += velocity.value.length() * time.delta_seconds();
+        if bullet.range.is_some_and(|range| bullet.travelled >= range) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
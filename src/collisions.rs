@@ -1,208 +1,407 @@
-use bevy::{app::AppExit, prelude::*, utils::{HashMap}};
+use std::ops::BitOr;
 
-use crate::{schedules::InGameSet, asteroids::Asteroid, player::Player, player::PlayerBullet, state::AppState, hud::{Lives, IMAGE_MARGIN, Score}, asset_loader::SceneAssets};
+use bevy::{prelude::*, utils::HashSet, window::PrimaryWindow, ecs::query::{ReadOnlyWorldQuery, WorldQuery}};
+
+use crate::{schedules::{InGameSet, DamageSet}, spatial_hash::SpatialHash, movement::{Velocity, Wraps, play_field_size, wrapped_offset}, asteroids::Asteroid, player::{Player, PlayerBullet, Invulnerable}, state::AppState, shield::PlayerShield, bullets::Bullet, health::{Damage, ContactDamage}, despawn::reset_resource};
+
+// Groups of colliders. A `Collider` is a member of some layers and only collides with colliders
+// whose layers are in its mask. Combine layers with `|`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CollisionLayers(u32);
+
+impl CollisionLayers {
+    pub const NONE: Self = Self(0);
+    pub const PLAYER: Self = Self(1 << 0);
+    pub const PLAYER_BULLET: Self = Self(1 << 1);
+    pub const ASTEROID: Self = Self(1 << 2);
+    pub const ENEMY: Self = Self(1 << 3);
+    pub const ENEMY_BULLET: Self = Self(1 << 4);
+    pub const PICKUP: Self = Self(1 << 5);
+
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl BitOr for CollisionLayers {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
 
 #[derive(Component, Debug)]
 pub struct Collider {
     pub radius: f32,
-    pub colliding_entities: Vec<Entity>,
+    // Layers this collider belongs to
+    pub layers: CollisionLayers,
+    // Layers this collider can collide with
+    pub mask: CollisionLayers,
 }
 
 impl Collider {
-    pub fn new(radius: f32) -> Self {
+    pub fn new(radius: f32, layers: CollisionLayers, mask: CollisionLayers) -> Self {
         Self {
             radius,
-            colliding_entities: vec![],
+            layers,
+            mask,
         }
     }
+
+    // Both colliders have to accept each other for the pair to be tested at all
+    pub fn interacts_with(&self, other: &Collider) -> bool {
+        self.layers.intersects(other.mask) && other.layers.intersects(self.mask)
+    }
+}
+
+// Sent once on the first tick two colliders touch. `a` is the collider on the lowest layer (so a
+// bullet always comes before the asteroid it hit), with ties broken by entity.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CollisionStarted {
+    pub a: Entity,
+    pub b: Entity,
+}
+
+// Sent once on the first tick two colliders stop touching, or one of them is despawned. Ordered
+// the same way as `CollisionStarted`.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CollisionEnded {
+    pub a: Entity,
+    pub b: Entity,
+}
+
+// Pairs of colliders that were touching at the end of the last tick. Like everything else about a
+// run, it starts out empty in every run.
+#[derive(Resource, Debug, Default)]
+struct Contacts(HashSet<(Entity, Entity)>);
+
+// Ships and the asteroids touching them, whether or not they can be hit, and the ships that
+// couldn't be hit on the last tick
+#[derive(Resource, Debug, Default)]
+struct PlayerContacts {
+    touching: HashSet<(Entity, Entity)>,
+    protected: HashSet<Entity>,
 }
 
 pub struct CollisionDetectionPlugin;
 
 impl Plugin for CollisionDetectionPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, 
+        app.add_event::<CollisionStarted>()
+            .add_event::<CollisionEnded>()
+            .init_resource::<Contacts>()
+            .init_resource::<PlayerContacts>()
+            .add_systems(OnEnter(AppState::InGame), (
+                reset_resource::<Contacts>,
+                reset_resource::<PlayerContacts>,
+            ));
+        app.add_systems(FixedUpdate, 
             collision_detection.run_if(in_state(AppState::InGame)).in_set(InGameSet::CollisionDetection),
         );
-        app.add_systems(Update, (
-            handle_asteroid_collisions,
-            handle_player_collisions,
+        app.add_systems(FixedUpdate, (
             handle_bullet_collisions,
-        ).run_if(in_state(AppState::InGame)).in_set(InGameSet::DespawnEntities),
+            handle_player_collisions,
+        ).run_if(in_state(AppState::InGame)).in_set(DamageSet::Deal),
         );
     }
 }
 
-fn collision_detection(mut query: Query<(Entity, &GlobalTransform, &mut Collider)>, bullets: Query<Entity, With<PlayerBullet>>) {
-    let mut colliding_entities: HashMap<Entity, Vec<Entity>> = HashMap::new();
+// One collider as it was at the end of this tick
+struct ColliderSnapshot<'a> {
+    entity: Entity,
+    position: Vec2,
+    // How far it moved during the tick
+    travel: Vec2,
+    collider: &'a Collider,
+    wraps: bool,
+}
+
+fn collision_detection(
+    // Transforms rather than global transforms, which are only brought up to date once a frame
+    query: Query<(Entity, &Transform, &Collider, Option<&Velocity>, Has<Wraps>)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut broadphase: Local<SpatialHash>,
+    mut contacts: ResMut<Contacts>,
+    mut started: EventWriter<CollisionStarted>,
+    mut ended: EventWriter<CollisionEnded>,
+    time: Res<Time>,
+) {
+    let window = window_query.get_single().unwrap();
+    let field = play_field_size(window);
+    let mut current_contacts = HashSet::default();
+
+    // Snapshot every collider so the broadphase can refer to them by index
+    let mut colliders: Vec<ColliderSnapshot> = query.iter()
+        .map(|(entity, transform, collider, velocity, wraps)| ColliderSnapshot {
+            entity,
+            position: transform.translation.xy(),
+            travel: velocity.map_or(Vec2::ZERO, |velocity| velocity.value.xy() * time.delta_seconds()),
+            collider,
+            wraps,
+        })
+        .collect();
+    // Query order depends on everything the world has been through, so put them in an order that
+    // only depends on the run itself. Collisions are then always reported in the same order.
+    colliders.sort_by(|a, b| {
+        a.position.x.total_cmp(&b.position.x)
+            .then(a.position.y.total_cmp(&b.position.y))
+            .then(a.collider.layers.cmp(&b.collider.layers))
+    });
 
-    // Get every combination of entity that has a collider
-    let mut iter = query.iter_combinations_mut();     
-    // For every combination
-    while let Some([(entity_a, transform_a, collider_a),(entity_b, transform_b, collider_b)]) = iter.fetch_next() {
-        // Get the distance between the two entities
-        let distance = transform_a.translation().xy().distance(transform_b.translation().xy());
+    // Rebuild the grid from this tick's positions
+    broadphase.clear();
+    for (index, snapshot) in colliders.iter().enumerate() {
+        // Colliders that can't hit anything don't need to be in the grid
+        if snapshot.collider.mask == CollisionLayers::NONE {
+            continue;
+        }
+        // Cover the whole path it took this tick, not just where it ended up
+        let center = snapshot.position - snapshot.travel / 2.0;
+        let radius = snapshot.collider.radius + snapshot.travel.length() / 2.0;
+        broadphase.insert(index, center, radius);
+        // A wrapping collider on an edge can also be hit through its ghost on the other side
+        if snapshot.wraps {
+            for ghost in ghost_positions(center, radius, field) {
+                broadphase.insert(index, ghost, radius);
+            }
+        }
+    }
+
+    // Only pairs that share a cell can possibly be touching
+    for (a, b) in broadphase.candidate_pairs() {
+        let (snapshot_a, snapshot_b) = (&colliders[a], &colliders[b]);
+        let (entity_a, collider_a) = (snapshot_a.entity, snapshot_a.collider);
+        let (entity_b, collider_b) = (snapshot_b.entity, snapshot_b.collider);
+        // Pairs the game doesn't care about, like asteroids bumping into each other
+        if !collider_a.interacts_with(collider_b) {
+            continue;
+        }
+        // Where b ended up relative to a. If either of them wraps, its ghost on the other side of
+        // the play field can collide too, so take the shortest way around.
+        let offset = if snapshot_a.wraps || snapshot_b.wraps {
+            wrapped_offset(snapshot_a.position, snapshot_b.position, field)
+        } else {
+            snapshot_b.position - snapshot_a.position
+        };
+        // Fast things like bullets can move further than an asteroid is wide in one tick, so take
+        // how close they came at any point during it rather than just at the end
+        let distance = closest_approach(offset, snapshot_b.travel - snapshot_a.travel);
         // If their colliders intersect
         if distance < collider_a.radius + collider_b.radius {
-            // Add entity_b to the vector of (key) entity_a in the newly created hashmap
-            colliding_entities.entry(entity_a).or_insert_with(Vec::new).push(entity_b);
+            let pair = if (collider_a.layers, entity_a) < (collider_b.layers, entity_b) {
+                (entity_a, entity_b)
+            } else {
+                (entity_b, entity_a)
+            };
+            current_contacts.insert(pair);
+            // Only the first tick of a contact is reported
+            if !contacts.0.contains(&pair) {
+                started.send(CollisionStarted { a: pair.0, b: pair.1 });
+            }
         }
     }
 
-    // For every entity (with a collider)
-    for (entity, _, mut collider) in query.iter_mut() {
-        // Remove all existing localized collisions
-        collider.colliding_entities.clear();
-        // If the entity is colliding with anything (and therefore in colliding_entities)
-        if let Some(collisions) = colliding_entities.get(&entity) {
-            // Add the vector of colliding entities from colliding_entities to the localized
-            // collisions of the entity
-            collider.colliding_entities.extend(collisions.iter().copied());
-        }
+    // Anything touching last tick but not this one has separated or been despawned
+    for &(a, b) in contacts.0.difference(&current_contacts) {
+        ended.send(CollisionEnded { a, b });
     }
+    contacts.0 = current_contacts;
 }
 
-fn handle_asteroid_collisions (
-    mut commands: Commands, 
-    asteroid_query: Query<(Entity, &Collider), With<Asteroid>>, 
-    bullet_query: Query<&PlayerBullet>,
-    mut player_query: Query<&mut Player>,
-    mut text_query: Query<&mut Text, With<Score>>,
-) {
-    // For every asteroid 
-    for (entity, collider) in asteroid_query.iter() {
-        // For every entry in its local database of collisions
-        for &collided_entity in collider.colliding_entities.iter() {
-            // if the entity stored in the list of colliding_entities exists in the query for
-            // colliders tagged with Asteroid it means an Asteroid has collided with an Asteroid.
-            if asteroid_query.get(collided_entity).is_ok() {
-                println!("Asteroid collided with asteroid");
-                continue;
-            }
-            // An asteroid has collided with something that is not another asteroid
-
-            let Ok(mut player) = player_query.get_single_mut() else { return; };
-            if bullet_query.get(collided_entity).is_ok() {
-                println!("Bullet and Asteroid Collision");
-                player.player_data.stats.asteroids_destroyed += 1;
-                player.player_data.stats.score += 100;
-
-                for mut text in text_query.iter_mut() {
-                    text.sections[0].value = format!("Score: {}", player.player_data.stats.score);
-                }
-                
-                commands.entity(entity).despawn_recursive();
-                commands.entity(collided_entity).despawn_recursive();
-            }
+// Smallest distance between two things that moved in straight lines during a tick and ended up
+// `offset` apart, where `relative_travel` is how far the second moved relative to the first
+fn closest_approach(offset: Vec2, relative_travel: Vec2) -> f32 {
+    let start = offset - relative_travel;
+    let length_squared = relative_travel.length_squared();
+    if length_squared == 0.0 {
+        return offset.length();
+    }
+    let t = (-start.dot(relative_travel) / length_squared).clamp(0.0, 1.0);
+    (start + relative_travel * t).length()
+}
 
-            // Player collision detection does not work in here for some reason
-        }
-    } 
+// Orders a colliding pair as (first, second) where `first` matches `first_query` and `second`
+// matches `second_query`. Returns None if the pair isn't that combination.
+pub fn match_pair<Q1: WorldQuery, F1: ReadOnlyWorldQuery, Q2: WorldQuery, F2: ReadOnlyWorldQuery>(
+    a: Entity,
+    b: Entity,
+    first_query: &Query<Q1, F1>,
+    second_query: &Query<Q2, F2>,
+) -> Option<(Entity, Entity)> {
+    if first_query.contains(a) && second_query.contains(b) {
+        Some((a, b))
+    } else if first_query.contains(b) && second_query.contains(a) {
+        Some((b, a))
+    } else {
+        None
+    }
 }
 
-fn handle_player_collisions (
+// Where the ghosts of a wrapping collider that overlaps the edges of the play field end up
+fn ghost_positions(position: Vec2, radius: f32, field: Vec2) -> Vec<Vec2> {
+    let shift_x = if position.x - radius < 0.0 {
+        field.x
+    } else if position.x + radius > field.x {
+        -field.x
+    } else {
+        0.0
+    };
+    let shift_y = if position.y - radius < 0.0 {
+        field.y
+    } else if position.y + radius > field.y {
+        -field.y
+    } else {
+        0.0
+    };
+
+    let mut ghosts = Vec::new();
+    if shift_x != 0.0 {
+        ghosts.push(position + Vec2::new(shift_x, 0.0));
+    }
+    if shift_y != 0.0 {
+        ghosts.push(position + Vec2::new(0.0, shift_y));
+    }
+    if shift_x != 0.0 && shift_y != 0.0 {
+        ghosts.push(position + Vec2::new(shift_x, shift_y));
+    }
+    ghosts
+}
+
+fn handle_bullet_collisions (
     mut commands: Commands, 
-    player_collider_query: Query<&Collider, With<Player>>, 
-    asteroid_query: Query<&Asteroid>,
+    mut collisions: EventReader<CollisionStarted>,
+    mut damage_events: EventWriter<Damage>,
+    mut bullet_query: Query<&mut Bullet, With<PlayerBullet>>, 
+    asteroid_query: Query<(), With<Asteroid>>,
     mut player_query: Query<&mut Player>,
-    mut lives_node: Query<Entity, With<Lives>>,
-    scene_assets: Res<SceneAssets>,
-    mut exit: EventWriter<AppExit>,
 ) {
-    let collider = player_collider_query.get_single().unwrap();
     let Ok(mut player) = player_query.get_single_mut() else { return; };
-    let lives = lives_node.get_single_mut().unwrap();
-    let life = UiImage::new(scene_assets.lives.clone());
-
-    // For every entry in its local database of collisions
-    for &collided_entity in collider.colliding_entities.iter() {
-        if asteroid_query.get(collided_entity).is_ok() {
-            println!("Player and Asteroid Collision");
-            commands.entity(lives).despawn_recursive();
-            if player.player_data.lives - 1 > 0 {
-                player.player_data.lives -= 1;
-                player.player_data.stats.asteroids_destroyed += 1;
-                player.player_data.stats.score += 50;
-            
-                // THIS IS INCREDIBLE STUPID BUT I DO NOT HAVE THE PATIENCE TO FIGURE OUT HOW TO
-                // PROPERLY DO IT AT THE MOMENT
-                commands.entity(collided_entity).despawn_recursive();
-                commands.spawn((NodeBundle {
-                    style: Style {
-                        display: Display::Flex,
-                        position_type: PositionType::Absolute,
-                        flex_direction: FlexDirection::Row,
-                        align_items: AlignItems::Center,
-                        right: Val::Px(0.0),
-                        bottom: Val::Px(0.0),
-                        ..default()
-                    },
-                    background_color: BackgroundColor(Color::rgb_u8(105, 105, 105)),
-                    ..default()
-                }, Lives)).with_children(
-                    |parent| {
-                        for _ in 0..player.player_data.lives {
-                            parent.spawn(ImageBundle {
-                                style: Style {
-                                    height: Val::Px(64.0),
-                                    width: Val::Px(64.0),
-                                    margin: UiRect::new(Val::Px(IMAGE_MARGIN), Val::Px(IMAGE_MARGIN), Val::Px(IMAGE_MARGIN), Val::Px(IMAGE_MARGIN)),
-                                    ..default()
-                                },
-                                image: life.clone(),
-                                ..default()
-                            });
-                        } 
-                    }
-                    );          
-            } else {
-                println!("\nGame Over!\n");
-                exit.send(AppExit);
-                return;
-            }
-            // commands.entity(entity).despawn_recursive();
+    // A bullet can touch two asteroids in the same tick, but is only used up once
+    let mut spent = HashSet::default();
 
-            }
+    for collision in collisions.read() {
+        let Some((bullet, asteroid)) = match_pair(collision.a, collision.b, &bullet_query, &asteroid_query) else {
+            continue;
+        };
+        if spent.contains(&bullet) {
+            continue;
+        }
+        let Ok(mut bullet_data) = bullet_query.get_mut(bullet) else {
+            continue;
+        };
+
+        damage_events.send(Damage { target: asteroid, source: bullet, amount: bullet_data.damage });
+
+        // A piercing bullet still only counts as one shot hitting
+        if bullet_data.hits == 0 {
+            player.player_data.stats.shots_hit += 1;
+        }
+        bullet_data.hits += 1;
+        // Piercing bullets keep going until they run out of targets to pass through
+        if bullet_data.pierce > 0 {
+            bullet_data.pierce -= 1;
+        } else {
+            commands.entity(bullet).despawn_recursive();
+            spent.insert(bullet);
         }
+    }
 }
 
-fn handle_bullet_collisions (
-    mut commands: Commands, 
-    bullet_query: Query<(Entity, &Collider), With<PlayerBullet>>, 
-    asteroid_query: Query<&Asteroid>,
-    mut player_query: Query<&mut Player>,
-    mut text_query: Query<&mut Text, With<Score>>,
+// Ships and asteroids that run into each other both take the other's contact damage. An asteroid
+// still inside the ship when its shield drops or its invulnerability runs out hits it right then.
+fn handle_player_collisions (
+    mut started: EventReader<CollisionStarted>,
+    mut ended: EventReader<CollisionEnded>,
+    mut damage_events: EventWriter<Damage>,
+    mut player_contacts: ResMut<PlayerContacts>,
+    ship_query: Query<(Entity, Has<PlayerShield>, Has<Invulnerable>), With<Player>>,
+    // Hits on a raised shield are handled by the shield instead, and a ship that just respawned
+    // can't be hit at all
+    player_query: Query<Option<&ContactDamage>, (With<Player>, Without<PlayerShield>, Without<Invulnerable>)>, 
+    asteroid_query: Query<Option<&ContactDamage>, With<Asteroid>>,
+    transform_query: Query<&Transform, With<Asteroid>>,
 ) {
-    // For every bullet 
-    for (entity, collider) in bullet_query.iter() {
-        // For every entry in its local database of collisions
-        for &collided_entity in collider.colliding_entities.iter() {
-            // if the entity stored in the list of colliding_entities exists in the query for
-            // colliders tagged with Asteroid it means an Asteroid has collided with an Asteroid.
-            if bullet_query.get(collided_entity).is_ok() {
-                println!("Asteroid collided with asteroid");
-                continue;
-            }
-            // An asteroid has collided with something that is not another asteroid
-
-            // It never gets here whenever Menu is the initial state
-            println!("It got here");
-
-            let Ok(mut player) = player_query.get_single_mut() else { return; };
-            if asteroid_query.get(collided_entity).is_ok() {
-                println!("Bullet and Asteroid Collision");
-                player.player_data.stats.asteroids_destroyed += 1;
-                player.player_data.stats.score += 100;
-
-                for mut text in text_query.iter_mut() {
-                    text.sections[0].value = format!("Score: {}", player.player_data.stats.score);
-                }
-                
-                commands.entity(entity).despawn_recursive();
-                commands.entity(collided_entity).despawn_recursive();
-            }
+    // Either one may already be despawned, so this can't check which is which
+    for collision in ended.read() {
+        player_contacts.touching.remove(&(collision.a, collision.b));
+        player_contacts.touching.remove(&(collision.b, collision.a));
+    }
 
-            // Player collision detection does not work in here for some reason
+    let mut hits = Vec::new();
+    for collision in started.read() {
+        if let Some(pair) = match_pair(collision.a, collision.b, &ship_query, &asteroid_query) {
+            player_contacts.touching.insert(pair);
+            hits.push(pair);
         }
-    } 
-}
\ No newline at end of file
+    }
+
+    let now_protected: HashSet<Entity> = ship_query.iter()
+        .filter(|&(_, shielded, invulnerable)| shielded || invulnerable)
+        .map(|(entity, _, _)| entity)
+        .collect();
+    let mut lingering: Vec<((Entity, Entity), Vec2)> = player_contacts.touching.iter()
+        .copied()
+        .filter(|pair| player_contacts.protected.contains(&pair.0) && !now_protected.contains(&pair.0) && !hits.contains(pair))
+        .filter_map(|pair| transform_query.get(pair.1).ok().map(|transform| (pair, transform.translation.xy())))
+        .collect();
+    // Neither the set's order nor entity ids are part of the run's state, so the hits are dealt in
+    // order of where the asteroids are, like collisions are reported
+    lingering.sort_by(|(_, a), (_, b)| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    hits.extend(lingering.into_iter().map(|(pair, _)| pair));
+    player_contacts.protected = now_protected;
+
+    for (player, asteroid) in hits {
+        if !player_query.contains(player) || !asteroid_query.contains(asteroid) {
+            continue;
+        }
+
+        if let Ok(Some(contact)) = asteroid_query.get(asteroid) {
+            damage_events.send(Damage { target: player, source: asteroid, amount: contact.amount });
+        }
+        if let Ok(Some(contact)) = player_query.get(player) {
+            damage_events.send(Damage { target: asteroid, source: player, amount: contact.amount });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELD: Vec2 = Vec2::new(800.0, 600.0);
+
+    #[test]
+    fn colliders_in_a_corner_have_ghosts_on_both_axes() {
+        let ghosts = ghost_positions(Vec2::new(5.0, 595.0), 10.0, FIELD);
+        assert_eq!(ghosts, vec![Vec2::new(805.0, 595.0), Vec2::new(5.0, -5.0), Vec2::new(805.0, -5.0)]);
+    }
+
+    #[test]
+    fn colliders_across_one_edge_have_one_ghost() {
+        assert_eq!(ghost_positions(Vec2::new(795.0, 300.0), 10.0, FIELD), vec![Vec2::new(-5.0, 300.0)]);
+        assert!(ghost_positions(Vec2::new(400.0, 300.0), 10.0, FIELD).is_empty());
+    }
+
+    #[test]
+    fn bullets_passing_through_a_small_asteroid_in_one_tick_hit_it() {
+        // The asteroid sits still at the origin, and the bullet went from 50 left of it to 50 right
+        let asteroid_radius = 10.0;
+        let bullet_radius = 5.0;
+        let offset = Vec2::new(50.0, 0.0);
+        let travel = Vec2::new(100.0, 0.0);
+
+        // Where they ended up alone would miss
+        assert!(offset.length() > asteroid_radius + bullet_radius);
+        assert_eq!(closest_approach(offset, travel), 0.0);
+    }
+
+    #[test]
+    fn bullets_passing_beside_an_asteroid_miss_it() {
+        assert_eq!(closest_approach(Vec2::new(50.0, 20.0), Vec2::new(100.0, 0.0)), 20.0);
+        // Still things are as far apart as they ended up
+        assert_eq!(closest_approach(Vec2::new(3.0, 4.0), Vec2::ZERO), 5.0);
+        // Moving apart, they were closest where they started
+        assert_eq!(closest_approach(Vec2::new(30.0, 0.0), Vec2::new(10.0, 0.0)), 20.0);
+    }
+}
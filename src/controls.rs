@@ -0,0 +1,273 @@
+use bevy::{prelude::*, ui::FocusPolicy};
+
+use crate::{
+    asset_loader::SceneAssets,
+    input::{Action, ActionBindings, ActionState, Binding},
+    menu::{NORMAL_BUTTON, HOVERED_BUTTON, PRESSED_BUTTON, TEXT_COLOR, spawn_button, spawn_small_button},
+};
+
+const ROW_LABEL_WIDTH: f32 = 170.0;
+const ROW_BINDINGS_WIDTH: f32 = 480.0;
+const CAPTURE_COLOR: Color = Color::rgb(1.0, 0.85, 0.4);
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<ControlsState>()
+            .add_systems(OnEnter(ControlsState::Open), setup_controls)
+            .add_systems(Update, (
+                // Closing goes first, so the key that cancels a capture doesn't close the screen too
+                close_controls,
+                capture_binding,
+                controls_menu,
+                refresh_controls,
+            ).chain().run_if(in_state(ControlsState::Open)))
+            .add_systems(OnExit(ControlsState::Open), cleanup_controls);
+    }
+}
+
+// Whether the controls screen is showing. It is opened from the settings screen and draws over it.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
+pub enum ControlsState {
+    #[default]
+    Closed,
+    Open,
+}
+
+#[derive(Resource)]
+struct ControlsData {
+    screen: Entity,
+    // The action waiting for an input to be pressed, if any
+    capturing: Option<Action>,
+    // Feedback on the last change, like a conflict or a failed save
+    status: String,
+}
+
+#[derive(Component, Clone, Copy)]
+enum ControlsButton {
+    Add(Action),
+    Clear(Action),
+    Reset,
+    Back,
+}
+
+// Lists what is bound to an action
+#[derive(Component)]
+struct BindingsText(Action);
+
+#[derive(Component)]
+struct StatusText;
+
+fn setup_controls(mut commands: Commands, bindings: Res<ActionBindings>, scene_assets: Res<SceneAssets>) {
+    let font = scene_assets.font.clone();
+    let text_style = |color| TextStyle {
+        font: font.clone(),
+        font_size: 18.0,
+        color,
+    };
+
+    let screen = commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(6.),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::rgb_u8(10, 10, 10)),
+            // Keep the settings screen underneath from reacting to clicks
+            focus_policy: FocusPolicy::Block,
+            z_index: ZIndex::Global(20),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Controls",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 48.0,
+                    color: TEXT_COLOR,
+                },
+            ).with_style(Style {
+                margin: UiRect::bottom(Val::Px(12.)),
+                ..default()
+            }));
+
+            for action in Action::ALL {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(8.),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(action.label(), text_style(TEXT_COLOR)).with_style(Style {
+                            width: Val::Px(ROW_LABEL_WIDTH),
+                            ..default()
+                        }));
+                        parent.spawn((TextBundle::from_section(bindings_label(&bindings, action), text_style(TEXT_COLOR)).with_style(Style {
+                            width: Val::Px(ROW_BINDINGS_WIDTH),
+                            ..default()
+                        }), BindingsText(action)));
+                        spawn_small_button(parent, &font, "Add", ControlsButton::Add(action));
+                        spawn_small_button(parent, &font, "Clear", ControlsButton::Clear(action));
+                    });
+            }
+
+            parent.spawn((TextBundle::from_section("", text_style(CAPTURE_COLOR)).with_style(Style {
+                margin: UiRect::vertical(Val::Px(8.)),
+                ..default()
+            }), StatusText));
+
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(16.),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_button(parent, &font, "Reset to defaults", ControlsButton::Reset);
+                    spawn_button(parent, &font, "Back", ControlsButton::Back);
+                });
+        })
+        .id();
+    commands.insert_resource(ControlsData { screen, capturing: None, status: String::new() });
+}
+
+fn bindings_label(bindings: &ActionBindings, action: Action) -> String {
+    let labels: Vec<String> = bindings.get(action).iter().map(|binding| binding.label()).collect();
+    if labels.is_empty() {
+        "Unbound".to_string()
+    } else {
+        labels.join(", ")
+    }
+}
+
+fn controls_menu(
+    mut next_state: ResMut<NextState<ControlsState>>,
+    mut controls: ResMut<ControlsData>,
+    mut bindings: ResMut<ActionBindings>,
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &ControlsButton),
+        Changed<Interaction>,
+    >,
+) {
+    for (interaction, mut color, button) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                match *button {
+                    ControlsButton::Add(action) => {
+                        controls.capturing = Some(action);
+                        controls.status = format!("Press a key, mouse button or gamepad button for {} (Escape cancels)", action.label());
+                    }
+                    ControlsButton::Clear(action) => {
+                        bindings.bindings.insert(action, Vec::new());
+                        controls.status = save_status(&bindings);
+                    }
+                    ControlsButton::Reset => {
+                        *bindings = ActionBindings::default();
+                        controls.status = save_status(&bindings);
+                    }
+                    ControlsButton::Back => next_state.set(ControlsState::Closed),
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn save_status(bindings: &ActionBindings) -> String {
+    match bindings.save() {
+        Ok(()) => "Saved".to_string(),
+        Err(error) => format!("Couldn't save controls: {}", error),
+    }
+}
+
+// Binds the next input pressed to the action being captured, unless another action already uses it
+fn capture_binding(
+    mut controls: ResMut<ControlsData>,
+    mut bindings: ResMut<ActionBindings>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+) {
+    let Some(action) = controls.capturing else {
+        return;
+    };
+    if keys.just_pressed(KeyCode::Escape) {
+        controls.capturing = None;
+        controls.status = String::new();
+        return;
+    }
+
+    let binding = keys.get_just_pressed().next().map(|&key| Binding::Key(key))
+        .or_else(|| mouse.get_just_pressed().next().map(|&button| Binding::Mouse(button)))
+        .or_else(|| gamepad_buttons.get_just_pressed().next().map(|button| Binding::GamepadButton(button.button_type)));
+    let Some(binding) = binding else {
+        return;
+    };
+    controls.capturing = None;
+
+    controls.status = match bindings.action_for(binding) {
+        Some(other) if other == action => format!("{} is already bound to {}", binding.label(), action.label()),
+        Some(other) => format!("{} is already bound to {}. Clear it there first.", binding.label(), other.label()),
+        None => {
+            bindings.bindings.entry(action).or_default().push(binding);
+            save_status(&bindings)
+        }
+    };
+}
+
+fn refresh_controls(
+    controls: Res<ControlsData>,
+    bindings: Res<ActionBindings>,
+    mut bindings_query: Query<(&mut Text, &BindingsText), Without<StatusText>>,
+    mut status_query: Query<&mut Text, With<StatusText>>,
+) {
+    if !controls.is_changed() && !bindings.is_changed() {
+        return;
+    }
+
+    for (mut text, bindings_text) in bindings_query.iter_mut() {
+        let action = bindings_text.0;
+        text.sections[0].value = if controls.capturing == Some(action) {
+            "...".to_string()
+        } else {
+            bindings_label(&bindings, action)
+        };
+        text.sections[0].style.color = if controls.capturing == Some(action) { CAPTURE_COLOR } else { TEXT_COLOR };
+    }
+    for mut text in status_query.iter_mut() {
+        text.sections[0].value = controls.status.clone();
+    }
+}
+
+fn close_controls(
+    mut next_state: ResMut<NextState<ControlsState>>,
+    controls: Res<ControlsData>,
+    actions: Res<ActionState>,
+) {
+    if controls.capturing.is_none() && actions.just_pressed(Action::Pause) {
+        next_state.set(ControlsState::Closed);
+    }
+}
+
+fn cleanup_controls(mut commands: Commands, controls: Res<ControlsData>) {
+    commands.entity(controls.screen).despawn_recursive();
+    commands.remove_resource::<ControlsData>();
+}
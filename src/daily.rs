@@ -0,0 +1,155 @@
+use std::{fmt, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{storage::data_dir, scores::{ScoreTable, high_scores_path}, weapon::WeaponKind};
+
+// Lives the ship starts a normal run with
+const STANDARD_LIVES: u32 = 3;
+// The daily challenge is the same for everyone: one life and the starting weapon, which can't be
+// swapped out
+const DAILY_LIVES: u32 = 1;
+const DAILY_WEAPON: WeaponKind = WeaponKind::Single;
+// Folder in the data directory with a score table for every day's challenge
+const DAILY_SCORES_DIR: &str = "daily";
+
+pub struct DailyPlugin;
+
+impl Plugin for DailyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunMode>();
+    }
+}
+
+// The rules the next run is played by. It stays the same for "Play again" until the menu picks
+// another one.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RunMode {
+    #[default]
+    Standard,
+    // The challenge for one day, which everyone plays from the same seed
+    Daily(Date),
+}
+
+impl RunMode {
+    pub fn lives(&self) -> u32 {
+        match self {
+            RunMode::Standard => STANDARD_LIVES,
+            RunMode::Daily(_) => DAILY_LIVES,
+        }
+    }
+
+    // The weapon the ship is stuck with, if it can't cycle through them
+    pub fn fixed_weapon(&self) -> Option<WeaponKind> {
+        match self {
+            RunMode::Standard => None,
+            RunMode::Daily(_) => Some(DAILY_WEAPON),
+        }
+    }
+
+    // The seed every run of this mode starts from, if it doesn't get a new one each time
+    pub fn seed(&self) -> Option<u64> {
+        match self {
+            RunMode::Standard => None,
+            RunMode::Daily(date) => Some(date.seed()),
+        }
+    }
+
+    // Where scores of this mode are kept. Each day's challenge has a table of its own.
+    pub fn score_table_path(&self) -> Option<PathBuf> {
+        match self {
+            RunMode::Standard => high_scores_path(),
+            RunMode::Daily(date) => daily_scores_path(*date),
+        }
+    }
+}
+
+// A calendar day
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    // Today in UTC, so players in every time zone get the same challenge at the same time
+    pub fn today() -> Self {
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs());
+        Self::from_days(seconds as i64 / 86_400)
+    }
+
+    // The date `days` after 1970-01-01, by Howard Hinnant's `civil_from_days`
+    fn from_days(days: i64) -> Self {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        // Months counted from March, so the leap day is at the end
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        Self {
+            year: year as i32,
+            month: month as u32,
+            day: day as u32,
+        }
+    }
+
+    // The date written out as digits, like 20240131, so it's easy to recognize on the game over screen
+    pub fn seed(&self) -> u64 {
+        (self.year.max(0) as u64) * 10_000 + u64::from(self.month) * 100 + u64::from(self.day)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+fn daily_scores_path(date: Date) -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(DAILY_SCORES_DIR).join(format!("{}.ron", date)))
+}
+
+// The scores saved for the challenge on `date`
+pub fn daily_scores(date: Date) -> ScoreTable {
+    daily_scores_path(date).map_or_else(ScoreTable::default, |path| ScoreTable::load(&path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> Date {
+        Date { year, month, day }
+    }
+
+    #[test]
+    fn days_since_epoch_convert_to_dates() {
+        assert_eq!(Date::from_days(0), date(1970, 1, 1));
+        assert_eq!(Date::from_days(-1), date(1969, 12, 31));
+        assert_eq!(Date::from_days(11_016), date(2000, 2, 29));
+        // 2100 isn't a leap year
+        assert_eq!(Date::from_days(47_540), date(2100, 2, 28));
+        assert_eq!(Date::from_days(47_541), date(2100, 3, 1));
+    }
+
+    // Every player's daily seed depends on this staying the same
+    #[test]
+    fn seed_is_the_date_as_digits() {
+        assert_eq!(date(2024, 1, 31).seed(), 20_240_131);
+        assert_eq!(date(2100, 3, 1).seed(), 21_000_301);
+        assert_eq!(RunMode::Daily(date(2000, 2, 29)).seed(), Some(20_000_229));
+        assert_eq!(RunMode::Standard.seed(), None);
+    }
+
+    #[test]
+    fn dates_display_as_iso_8601() {
+        assert_eq!(date(2024, 1, 31).to_string(), "2024-01-31");
+    }
+}
@@ -1,6 +1,6 @@
 use bevy::{prelude::*, window::PrimaryWindow};
 
-use crate::{schedules::InGameSet, state::AppState, MainCamera};
+use crate::{schedules::InGameSet, state::AppState, movement::{Velocity, Wraps}};
 
 const DESPAWN_DISTANCE: f32 = 750.0;
 
@@ -8,25 +8,65 @@ pub struct DespawnPlugin;
 
 impl Plugin for DespawnPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, despawn_far_entities.run_if(in_state(AppState::InGame)).in_set(InGameSet::DespawnEntities),);
+        app.add_systems(FixedUpdate, (
+            despawn_far_entities,
+            despawn_expired_entities,
+        ).run_if(in_state(AppState::InGame)).in_set(InGameSet::DespawnEntities),);
+        // A lost run stays on screen behind the game over summary, so it is only cleared once
+        // the player leaves that screen
+        app.add_systems(OnExit(AppState::InGame), despawn_in_game_entities.run_if(not(in_state(AppState::GameOver))))
+            .add_systems(OnExit(AppState::GameOver), despawn_in_game_entities);
     }
 }
 
+// Tags everything spawned during a run. All of it is despawned when the run ends.
+#[derive(Component, Debug)]
+pub struct InGameScoped;
+
+// Despawns the entity once its timer runs out
+#[derive(Component, Debug)]
+pub struct Lifetime {
+    pub timer: Timer,
+}
+
+impl Lifetime {
+    pub fn new(seconds: f32) -> Self {
+        Self {
+            timer: Timer::from_seconds(seconds, TimerMode::Once),
+        }
+    }
+}
+
+// Wrapping entities never leave the play field, so only the ones that don't wrap are cleaned up here
 fn despawn_far_entities(
-    mut commands: Commands, 
-    query: Query<(Entity, &GlobalTransform), Without<MainCamera>>,
+    mut commands: Commands,
+    query: Query<(Entity, &Transform), (With<Velocity>, Without<Wraps>)>,
     window_query: Query<&Window, With<PrimaryWindow>>,
-
 ) {
     let window = window_query.get_single().unwrap();
     for (entity, transform) in query.iter() {
-        let distance = transform.translation().distance(Vec3::new(window.width() / 2.0, window.height() / 2.0, 0.0));
-        // println!("Distance: {}", distance);
-
+        let distance = transform.translation.distance(Vec3::new(window.width() / 2.0, window.height() / 2.0, 0.0));
         if distance > DESPAWN_DISTANCE {
-            println!("Despawned entity");
             commands.entity(entity).despawn_recursive();
         }
     }
 }
 
+fn despawn_expired_entities(mut commands: Commands, mut query: Query<(Entity, &mut Lifetime)>, time: Res<Time>) {
+    for (entity, mut lifetime) in query.iter_mut() {
+        if lifetime.timer.tick(time.delta()).just_finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn despawn_in_game_entities(mut commands: Commands, query: Query<Entity, With<InGameScoped>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Puts a run-scoped resource back to its starting value, so every run starts the same way
+pub fn reset_resource<R: Resource + Default>(mut commands: Commands) {
+    commands.insert_resource(R::default());
+}
@@ -0,0 +1,403 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::{
+    state::AppState,
+    asset_loader::SceneAssets,
+    player::Player,
+    replay::ReplayCheck,
+    rng::GameRng,
+    daily::RunMode,
+    scores::{ScoreEntry, ScoreTable, MAX_SCORES, INITIALS_LEN},
+    input::{Action, ActionState, ActionBindings},
+    menu::{NORMAL_BUTTON, HOVERED_BUTTON, PRESSED_BUTTON, TEXT_COLOR, spawn_button, spawn_small_button},
+};
+
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(OnEnter(AppState::GameOver), setup_game_over)
+            // Initials saved by either one are gone before the other looks, so they're saved once
+            .add_systems(Update, (
+                enter_initials.run_if(resource_exists::<InitialsEntry>()),
+                game_over_menu,
+            ).chain().run_if(in_state(AppState::GameOver)))
+            .add_systems(OnExit(AppState::GameOver), cleanup_game_over);
+    }
+}
+
+#[derive(Resource)]
+struct GameOverData {
+    screen: Entity,
+}
+
+#[derive(Component)]
+enum GameOverButton {
+    PlayAgain,
+    MainMenu,
+}
+
+// Saves the initials as they are. It and the Enter key work however the actions are bound.
+#[derive(Component)]
+struct ConfirmInitialsButton;
+
+// Where the initials are entered, and where the score's place on the table shows afterwards
+#[derive(Component)]
+struct InitialsPanel;
+
+// One letter of the initials being entered
+#[derive(Component)]
+struct InitialSlot(usize);
+
+// A score good enough for its table, waiting for the player's initials before it's saved
+#[derive(Resource, Debug)]
+struct InitialsEntry {
+    path: PathBuf,
+    entry: ScoreEntry,
+    letters: [u8; INITIALS_LEN],
+    // The letter being changed
+    cursor: usize,
+}
+
+impl InitialsEntry {
+    fn new(path: PathBuf, entry: ScoreEntry) -> Self {
+        Self {
+            path,
+            entry,
+            letters: [b'A'; INITIALS_LEN],
+            cursor: 0,
+        }
+    }
+
+    // Steps the current letter through the alphabet, wrapping around at either end
+    fn cycle_letter(&mut self, step: i32) {
+        let letter = &mut self.letters[self.cursor];
+        *letter = b'A' + (*letter as i32 - b'A' as i32 + step).rem_euclid(26) as u8;
+    }
+
+    fn initials(&self) -> String {
+        self.letters.iter().map(|&letter| letter as char).collect()
+    }
+
+    // Adds the score to its table with the initials entered so far, and returns its place there
+    fn save(&self) -> Option<usize> {
+        let mut score = self.entry.clone();
+        score.initials = self.initials();
+        // Loaded again in case another copy of the game saved a score since this run ended
+        let mut table = ScoreTable::load(&self.path);
+        let rank = table.insert(score)?;
+        table.save(&self.path);
+        Some(rank)
+    }
+}
+
+fn setup_game_over(
+    mut commands: Commands,
+    player_query: Query<&Player>,
+    replay_check: Option<Res<ReplayCheck>>,
+    rng: Res<GameRng>,
+    run_mode: Res<RunMode>,
+    bindings: Res<ActionBindings>,
+    scene_assets: Res<SceneAssets>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let stats = &player.player_data.stats;
+    let font = scene_assets.font.clone();
+
+    let mut lines = vec![
+        format!("Score: {}", stats.score),
+        format!("Asteroids destroyed: {}", stats.asteroids_destroyed),
+        format!("Shots fired: {}", stats.shots_fired),
+        format!("Accuracy: {:.1}%", stats.accuracy()),
+        format!("Level reached: {}", stats.level),
+        // Enough to play the same run again with --seed
+        format!("Seed: {}", rng.seed()),
+    ];
+    // Only there when the run was a replay being watched
+    match replay_check.as_deref() {
+        Some(ReplayCheck::Matched) => lines.push("Replay matched the recorded run".to_string()),
+        Some(ReplayCheck::Diverged) => lines.push("Replay diverged from the recorded run".to_string()),
+        None => {}
+    }
+    if let RunMode::Daily(date) = *run_mode {
+        lines.push(format!("Daily challenge {}", date));
+    }
+
+    // Watching a replay doesn't count as another go, so it never makes it onto a table
+    let mut entry = None;
+    if let Some(path) = run_mode.score_table_path().filter(|_| replay_check.is_none()) {
+        let table = ScoreTable::load(&path);
+        if let Some(best) = table.best() {
+            lines.push(best_line(*run_mode, best));
+        }
+        if table.qualifies(stats.score) {
+            entry = Some(InitialsEntry::new(path, ScoreEntry::new(String::new(), stats)));
+        } else {
+            lines.push(unranked_line(*run_mode));
+        }
+    }
+
+    // The play field stays where it was underneath, this just dims it
+    let screen = commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(8.),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::rgba_u8(0, 0, 0, 180)),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Game Over",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 64.0,
+                    color: TEXT_COLOR,
+                },
+            ).with_style(Style {
+                margin: UiRect::bottom(Val::Px(16.)),
+                ..default()
+            }));
+
+            for line in lines {
+                spawn_line(parent, &font, line);
+            }
+
+            if let Some(entry) = &entry {
+                spawn_initials_panel(parent, &font, entry, &bindings);
+            }
+
+            // Leaving with initials still being entered saves them as they are
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        margin: UiRect::top(Val::Px(24.)),
+                        column_gap: Val::Px(16.),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_button(parent, &font, "Play again", GameOverButton::PlayAgain);
+                    spawn_button(parent, &font, "Main menu", GameOverButton::MainMenu);
+                });
+        })
+        .id();
+    commands.insert_resource(GameOverData { screen });
+    if let Some(entry) = entry {
+        commands.insert_resource(entry);
+    }
+}
+
+fn spawn_line(parent: &mut ChildBuilder, font: &Handle<Font>, line: String) {
+    parent.spawn(TextBundle::from_section(
+        line,
+        TextStyle {
+            font: font.clone(),
+            font_size: 24.0,
+            color: TEXT_COLOR,
+        },
+    ));
+}
+
+fn spawn_initials_panel(parent: &mut ChildBuilder, font: &Handle<Font>, entry: &InitialsEntry, bindings: &ActionBindings) {
+    parent
+        .spawn((NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(8.),
+                margin: UiRect::top(Val::Px(16.)),
+                ..default()
+            },
+            ..default()
+        }, InitialsPanel))
+        .with_children(|parent| {
+            spawn_line(parent, font, "New high score! Enter your initials".to_string());
+
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(16.),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for slot in 0..INITIALS_LEN {
+                        parent.spawn((TextBundle::from_section(
+                            (entry.letters[slot] as char).to_string(),
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 48.0,
+                                color: slot_color(entry, slot),
+                            },
+                        ), InitialSlot(slot)));
+                    }
+                    spawn_small_button(parent, font, "OK", ConfirmInitialsButton);
+                });
+
+            parent.spawn(TextBundle::from_section(
+                format!(
+                    "{}/{} change letter   {}/{} move   {} next   Enter to save",
+                    bindings.hint(Action::Thrust),
+                    bindings.hint(Action::Reverse),
+                    bindings.hint(Action::RotateLeft),
+                    bindings.hint(Action::RotateRight),
+                    bindings.hint(Action::Fire),
+                ),
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 18.0,
+                    color: TEXT_COLOR,
+                },
+            ));
+        });
+}
+
+// The letter being changed stands out from the others
+fn slot_color(entry: &InitialsEntry, slot: usize) -> Color {
+    if slot == entry.cursor { PRESSED_BUTTON } else { TEXT_COLOR }
+}
+
+fn best_line(run_mode: RunMode, best: u32) -> String {
+    match run_mode {
+        RunMode::Standard => format!("High score: {}", best),
+        RunMode::Daily(_) => format!("Today's best: {}", best),
+    }
+}
+
+fn rank_line(run_mode: RunMode, rank: usize) -> String {
+    match run_mode {
+        RunMode::Standard => format!("High score #{}", rank + 1),
+        RunMode::Daily(_) => format!("Today's rank: #{}", rank + 1),
+    }
+}
+
+fn unranked_line(run_mode: RunMode) -> String {
+    match run_mode {
+        RunMode::Standard => format!("Not in the top {}", MAX_SCORES),
+        RunMode::Daily(_) => format!("Not in today's top {}", MAX_SCORES),
+    }
+}
+
+// Arcade style: thrust and reverse step through the alphabet, the rotate keys move between letters
+// and fire moves on, saving the score after the last letter. Enter or the OK button save it at any
+// point.
+fn enter_initials(
+    mut commands: Commands,
+    mut entry: ResMut<InitialsEntry>,
+    actions: Res<ActionState>,
+    keys: Res<Input<KeyCode>>,
+    run_mode: Res<RunMode>,
+    scene_assets: Res<SceneAssets>,
+    mut slot_query: Query<(&InitialSlot, &mut Text)>,
+    panel_query: Query<Entity, With<InitialsPanel>>,
+    mut confirm_query: Query<(&Interaction, &mut BackgroundColor), (With<ConfirmInitialsButton>, Changed<Interaction>)>,
+) {
+    let mut confirmed = keys.just_pressed(KeyCode::Return);
+    for (interaction, mut color) in confirm_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                confirmed = true;
+            }
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    }
+
+    if actions.just_pressed(Action::Thrust) {
+        entry.cycle_letter(1);
+    }
+    if actions.just_pressed(Action::Reverse) {
+        entry.cycle_letter(-1);
+    }
+    if actions.just_pressed(Action::RotateLeft) {
+        entry.cursor = entry.cursor.saturating_sub(1);
+    }
+    if actions.just_pressed(Action::RotateRight) {
+        entry.cursor = (entry.cursor + 1).min(INITIALS_LEN - 1);
+    }
+
+    if actions.just_pressed(Action::Fire) {
+        if entry.cursor + 1 < INITIALS_LEN {
+            entry.cursor += 1;
+        } else {
+            confirmed = true;
+        }
+    }
+
+    if confirmed {
+        let line = match entry.save() {
+            Some(rank) => rank_line(*run_mode, rank),
+            None => unranked_line(*run_mode),
+        };
+        commands.remove_resource::<InitialsEntry>();
+        for panel in panel_query.iter() {
+            commands.entity(panel)
+                .despawn_descendants()
+                .with_children(|parent| spawn_line(parent, &scene_assets.font, line.clone()));
+        }
+        return;
+    }
+
+    if !entry.is_changed() {
+        return;
+    }
+    for (slot, mut text) in slot_query.iter_mut() {
+        text.sections[0].value = (entry.letters[slot.0] as char).to_string();
+        text.sections[0].style.color = slot_color(&entry, slot.0);
+    }
+}
+
+fn game_over_menu(
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<AppState>>,
+    entry: Option<Res<InitialsEntry>>,
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &GameOverButton),
+        Changed<Interaction>,
+    >,
+) {
+    for (interaction, mut color, button) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                if let Some(entry) = &entry {
+                    entry.save();
+                    commands.remove_resource::<InitialsEntry>();
+                }
+                match button {
+                    GameOverButton::PlayAgain => next_state.set(AppState::InGame),
+                    GameOverButton::MainMenu => next_state.set(AppState::Menu),
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn cleanup_game_over(mut commands: Commands, game_over: Option<Res<GameOverData>>) {
+    if let Some(game_over) = game_over {
+        commands.entity(game_over.screen).despawn_recursive();
+        commands.remove_resource::<GameOverData>();
+    }
+    commands.remove_resource::<InitialsEntry>();
+}
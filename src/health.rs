@@ -0,0 +1,117 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{schedules::{InGameSet, DamageSet}, state::AppState};
+
+// How long a damaged sprite flashes for
+const DAMAGE_FLASH_TIME: f32 = 0.2;
+// How many times a second it flashes
+const DAMAGE_FLASH_RATE: f32 = 20.0;
+const DAMAGE_FLASH_COLOR: Color = Color::rgb(1.0, 0.3, 0.3);
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Damage>()
+            .add_event::<Destroyed>();
+        app.add_systems(FixedUpdate,
+            apply_damage.run_if(in_state(AppState::InGame)).in_set(DamageSet::Apply),
+        );
+        app.add_systems(FixedUpdate,
+            update_damage_flash.run_if(in_state(AppState::InGame)).in_set(InGameSet::EntityUpdates),
+        );
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    pub fn new(max: u32) -> Self {
+        Self { current: max, max }
+    }
+}
+
+// Damage dealt to whatever this collider runs into
+#[derive(Component, Debug)]
+pub struct ContactDamage {
+    pub amount: u32,
+}
+
+impl ContactDamage {
+    pub fn new(amount: u32) -> Self {
+        Self { amount }
+    }
+}
+
+// Asks for `amount` to be taken off the target's health. `source` is whatever dealt it, like a
+// bullet or the asteroid that rammed a ship.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Damage {
+    pub target: Entity,
+    pub source: Entity,
+    pub amount: u32,
+}
+
+// Sent once when an entity's health runs out. Whatever owns the entity decides what happens to it.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Destroyed {
+    pub entity: Entity,
+    pub source: Entity,
+}
+
+// Tints the sprite for a moment after taking damage
+#[derive(Component, Debug)]
+struct DamageFlash {
+    timer: Timer,
+    // The color to go back to afterwards
+    color: Color,
+}
+
+fn apply_damage(
+    mut commands: Commands,
+    mut damage_events: EventReader<Damage>,
+    mut destroyed_events: EventWriter<Destroyed>,
+    mut query: Query<(&mut Health, Option<&mut DamageFlash>, Option<&Sprite>)>,
+) {
+    // Anything can take several hits in one tick, but is only destroyed once
+    let mut destroyed = HashSet::default();
+
+    for damage in damage_events.read() {
+        if destroyed.contains(&damage.target) {
+            continue;
+        }
+        let Ok((mut health, flash, sprite)) = query.get_mut(damage.target) else {
+            continue;
+        };
+
+        health.current = health.current.saturating_sub(damage.amount);
+        if health.current == 0 {
+            destroyed_events.send(Destroyed { entity: damage.target, source: damage.source });
+            destroyed.insert(damage.target);
+        } else if let Some(mut flash) = flash {
+            flash.timer.reset();
+        } else if let Some(sprite) = sprite {
+            commands.entity(damage.target).insert(DamageFlash {
+                timer: Timer::from_seconds(DAMAGE_FLASH_TIME, TimerMode::Once),
+                color: sprite.color,
+            });
+        }
+    }
+}
+
+fn update_damage_flash(mut commands: Commands, mut query: Query<(Entity, &mut DamageFlash, &mut Sprite)>, time: Res<Time>) {
+    for (entity, mut flash, mut sprite) in query.iter_mut() {
+        if flash.timer.tick(time.delta()).finished() {
+            sprite.color = flash.color;
+            commands.entity(entity).remove::<DamageFlash>();
+            continue;
+        }
+
+        let flash_on = ((flash.timer.elapsed_secs() * DAMAGE_FLASH_RATE) as u32).is_multiple_of(2);
+        sprite.color = if flash_on { DAMAGE_FLASH_COLOR } else { flash.color };
+    }
+}
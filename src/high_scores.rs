@@ -0,0 +1,173 @@
+use bevy::prelude::*;
+
+use crate::{
+    state::AppState,
+    asset_loader::SceneAssets,
+    input::{Action, ActionState},
+    daily::{Date, daily_scores},
+    scores::{ScoreTable, high_scores_path},
+    menu::{NORMAL_BUTTON, HOVERED_BUTTON, PRESSED_BUTTON, TEXT_COLOR, spawn_button},
+};
+
+pub struct HighScoresPlugin;
+
+impl Plugin for HighScoresPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(OnEnter(AppState::HighScores), setup_high_scores)
+            .add_systems(Update, (
+                high_scores_menu,
+                close_high_scores,
+            ).run_if(in_state(AppState::HighScores)))
+            .add_systems(OnExit(AppState::HighScores), cleanup_high_scores);
+    }
+}
+
+#[derive(Resource)]
+struct HighScoresData {
+    screen: Entity,
+}
+
+#[derive(Component)]
+enum HighScoresButton {
+    Back,
+}
+
+// The all time table, with today's daily challenge next to it
+fn setup_high_scores(mut commands: Commands, scene_assets: Res<SceneAssets>) {
+    let font = scene_assets.font.clone();
+    let high_scores = high_scores_path().map_or_else(ScoreTable::default, |path| ScoreTable::load(&path));
+    let today = Date::today();
+    let daily = daily_scores(today);
+
+    let screen = commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(24.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "High Scores",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 64.0,
+                    color: TEXT_COLOR,
+                },
+            ));
+
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(64.),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_score_column(parent, &font, "All time", &high_scores);
+                    spawn_score_column(parent, &font, &format!("Daily challenge {}", today), &daily);
+                });
+
+            spawn_button(parent, &font, "Back", HighScoresButton::Back);
+        })
+        .id();
+    commands.insert_resource(HighScoresData { screen });
+}
+
+fn spawn_score_column(parent: &mut ChildBuilder, font: &Handle<Font>, title: &str, table: &ScoreTable) {
+    let text_style = TextStyle {
+        font: font.clone(),
+        font_size: 24.0,
+        color: TEXT_COLOR,
+    };
+
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Start,
+                row_gap: Val::Px(6.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                title,
+                TextStyle {
+                    font_size: 28.0,
+                    ..text_style.clone()
+                },
+            ).with_style(Style {
+                margin: UiRect::bottom(Val::Px(8.)),
+                ..default()
+            }));
+
+            if table.entries.is_empty() {
+                parent.spawn(TextBundle::from_section("No scores yet", text_style.clone()));
+            }
+
+            for (rank, entry) in table.entries.iter().enumerate() {
+                // Fixed width cells, so the columns line up
+                parent
+                    .spawn(NodeBundle::default())
+                    .with_children(|parent| {
+                        for (text, width) in [
+                            (format!("{}.", rank + 1), 48.),
+                            (entry.initials.clone(), 80.),
+                            (entry.score.to_string(), 120.),
+                            (format!("Level {}", entry.level), 100.),
+                        ] {
+                            parent.spawn(TextBundle::from_section(text, text_style.clone()).with_style(Style {
+                                width: Val::Px(width),
+                                ..default()
+                            }));
+                        }
+                    });
+            }
+        });
+}
+
+fn high_scores_menu(
+    mut next_state: ResMut<NextState<AppState>>,
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &HighScoresButton),
+        Changed<Interaction>,
+    >,
+) {
+    for (interaction, mut color, button) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                match button {
+                    HighScoresButton::Back => next_state.set(AppState::Menu),
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn close_high_scores(mut next_state: ResMut<NextState<AppState>>, actions: Res<ActionState>) {
+    if actions.just_pressed(Action::Pause) {
+        next_state.set(AppState::Menu);
+    }
+}
+
+fn cleanup_high_scores(mut commands: Commands, high_scores: Res<HighScoresData>) {
+    commands.entity(high_scores.screen).despawn_recursive();
+    commands.remove_resource::<HighScoresData>();
+}
@@ -1,9 +1,14 @@
 use bevy::{prelude::*};
 
-use crate::{state::AppState, asset_loader::SceneAssets, schedules::InGameSet};
+use crate::{state::AppState, asset_loader::SceneAssets, schedules::InGameSet, despawn::InGameScoped, player::Player, shield::{ShieldEnergy, PlayerShield}, weapon::Weapon, health::Health, hyperspace::HyperspaceCooldown, input::{Action, ActionBindings}, daily::RunMode};
 
 pub const IMAGE_MARGIN: f32 = 8.0;
 pub const IMAGE_SIZE: f32 = 32.0;
+const SHIELD_METER_WIDTH: f32 = 120.0;
+const SHIELD_METER_HEIGHT: f32 = 8.0;
+const SHIELD_METER_COLOR: Color = Color::rgb(0.45, 0.75, 1.0);
+// While the shield is up the meter brightens, so it's clear it's draining
+const SHIELD_METER_ACTIVE_COLOR: Color = Color::rgb(0.75, 0.95, 1.0);
 
 pub struct HudPlugin;
 
@@ -11,30 +16,46 @@ impl Plugin for HudPlugin {
     fn build(&self, app: &mut App) {
         app
             .add_systems(OnEnter(AppState::InGame), spawn_hud.run_if(in_state(AppState::InGame)).after(InGameSet::CollisionDetection))
-            /* .add_systems(Update, (
+            .add_systems(Update, (
                 update_hud,
-            ).run_if(in_state(AppState::InGame))) */
-            .add_systems(OnExit(AppState::InGame), cleanup_hud);
+                update_lives,
+                update_hull,
+                update_hyperspace_indicator,
+                update_shield_meter,
+                update_weapon_label,
+            ).run_if(in_state(AppState::InGame)));
     }
 }
 
 #[derive(Component)]
 pub struct Lives;
 
-#[derive(Resource)]
-pub struct ScoreData {
-    txt_score: Entity,
-}
-
 #[derive(Component)]
 pub struct Score;
 
+#[derive(Component)]
+pub struct Level;
+
+#[derive(Component)]
+pub struct WeaponLabel;
+
+#[derive(Component)]
+pub struct Hull;
+
+#[derive(Component)]
+pub struct HyperspaceIndicator;
+
+// The filled part of the shield energy bar
+#[derive(Component)]
+pub struct ShieldMeter;
+
 fn spawn_hud(
     mut commands: Commands, 
     scene_assets: Res<SceneAssets>,
+    run_mode: Res<RunMode>,
 ) {
     let font_res = scene_assets.font.clone();
-    let life = UiImage::new(scene_assets.lives.clone());
+    let life = scene_assets.lives.clone();
 
     commands.spawn((NodeBundle {
         style: Style {
@@ -50,27 +71,12 @@ fn spawn_hud(
         },
         background_color: BackgroundColor(Color::rgba_u8(0, 0, 0, 155)),
         ..default()
-        }, Lives)).with_children(
-            |parent| {
-                for _ in 0..3 {
-                    parent.spawn(ImageBundle {
-                        style: Style {
-                            height: Val::Px(64.0),
-                            width: Val::Px(64.0),
-                            margin: UiRect::new(Val::Px(IMAGE_MARGIN), Val::Px(IMAGE_MARGIN), Val::Px(IMAGE_MARGIN), Val::Px(IMAGE_MARGIN)),
-                            ..default()
-                        },
-                        image: life.clone(),
-                        ..default()
-                    });
-                } 
-            }
-    );
-    let txt_score = commands.spawn((
+        }, Lives, InGameScoped)).with_children(|parent| spawn_life_icons(parent, &life, run_mode.lives()));
+    commands.spawn((
         TextBundle::from_section(
             "Score: 0",
             TextStyle {
-                font: font_res,
+                font: font_res.clone(),
                 font_size: 18.0,
                 ..default()
             },
@@ -80,13 +86,218 @@ fn spawn_hud(
             top: Val::Px(10.0),
             left: Val::Px(10.0),
             ..default()
-        }), Score),
-    ).id();
-    commands.insert_resource(ScoreData { txt_score });
+        }), Score, InGameScoped),
+    );
+    commands.spawn((
+        TextBundle::from_section(
+            "Wave: 1",
+            TextStyle {
+                font: font_res.clone(),
+                font_size: 18.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(34.0),
+            left: Val::Px(10.0),
+            ..default()
+        }), Level, InGameScoped),
+    );
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font_res.clone(),
+                font_size: 18.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(74.0),
+            left: Val::Px(10.0),
+            ..default()
+        }), WeaponLabel, InGameScoped),
+    );
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font_res.clone(),
+                font_size: 18.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(98.0),
+            left: Val::Px(10.0),
+            ..default()
+        }), Hull, InGameScoped),
+    );
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font_res.clone(),
+                font_size: 18.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(122.0),
+            left: Val::Px(10.0),
+            ..default()
+        }), HyperspaceIndicator, InGameScoped),
+    );
+    commands.spawn((NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(58.0),
+            left: Val::Px(10.0),
+            width: Val::Px(SHIELD_METER_WIDTH),
+            height: Val::Px(SHIELD_METER_HEIGHT),
+            ..default()
+        },
+        background_color: BackgroundColor(Color::rgba_u8(255, 255, 255, 40)),
+        ..default()
+    }, InGameScoped)).with_children(|parent| {
+        parent.spawn((NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            background_color: BackgroundColor(SHIELD_METER_COLOR),
+            ..default()
+        }, ShieldMeter));
+    });
 }
 
-fn cleanup_hud(mut commands: Commands, ) {
+// Keeps the score and wave text in line with the player's stats
+fn update_hud(
+    player_query: Query<&Player, Changed<Player>>,
+    mut score_query: Query<&mut Text, (With<Score>, Without<Level>)>,
+    mut level_query: Query<&mut Text, (With<Level>, Without<Score>)>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let stats = &player.player_data.stats;
 
+    for mut text in score_query.iter_mut() {
+        text.sections[0].value = format!("Score: {}", stats.score);
+    }
+    for mut text in level_query.iter_mut() {
+        text.sections[0].value = format!("Wave: {}", stats.level);
+    }
 }
 
+fn update_shield_meter(
+    player_query: Query<(&ShieldEnergy, Has<PlayerShield>)>,
+    mut meter_query: Query<(&mut Style, &mut BackgroundColor), With<ShieldMeter>>,
+) {
+    let Ok((energy, shielded)) = player_query.get_single() else {
+        return;
+    };
+
+    for (mut style, mut color) in meter_query.iter_mut() {
+        style.width = Val::Percent(energy.current / energy.max * 100.0);
+        *color = if shielded { SHIELD_METER_ACTIVE_COLOR } else { SHIELD_METER_COLOR }.into();
+    }
+}
+
+fn update_weapon_label(
+    player_query: Query<Ref<Weapon>, With<Player>>,
+    mut label_query: Query<&mut Text, With<WeaponLabel>>,
+    bindings: Res<ActionBindings>,
+    run_mode: Res<RunMode>,
+) {
+    let Ok(weapon) = player_query.get_single() else {
+        return;
+    };
+    if !weapon.is_changed() && !bindings.is_changed() {
+        return;
+    }
 
+    for mut text in label_query.iter_mut() {
+        // No key hint when the weapon can't be cycled
+        text.sections[0].value = match run_mode.fixed_weapon() {
+            Some(_) => format!("Weapon: {}", weapon.kind.name()),
+            None => format!("Weapon: {} [{}]", weapon.kind.name(), bindings.hint(Action::CycleWeapon)),
+        };
+    }
+}
+
+fn spawn_life_icons(parent: &mut ChildBuilder, life: &Handle<Image>, count: u32) {
+    for _ in 0..count {
+        parent.spawn(ImageBundle {
+            style: Style {
+                height: Val::Px(64.0),
+                width: Val::Px(64.0),
+                margin: UiRect::new(Val::Px(IMAGE_MARGIN), Val::Px(IMAGE_MARGIN), Val::Px(IMAGE_MARGIN), Val::Px(IMAGE_MARGIN)),
+                ..default()
+            },
+            image: UiImage::new(life.clone()),
+            ..default()
+        });
+    }
+}
+
+// Shows one icon per remaining life
+fn update_lives(
+    mut commands: Commands,
+    player_query: Query<&Player, Changed<Player>>,
+    lives_query: Query<(Entity, Option<&Children>), With<Lives>>,
+    scene_assets: Res<SceneAssets>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let lives = player.player_data.lives;
+
+    for (entity, children) in lives_query.iter() {
+        if children.map_or(0, |children| children.len()) == lives as usize {
+            continue;
+        }
+        commands.entity(entity)
+            .despawn_descendants()
+            .with_children(|parent| spawn_life_icons(parent, &scene_assets.lives, lives));
+    }
+}
+
+fn update_hull(
+    player_query: Query<&Health, (With<Player>, Changed<Health>)>,
+    mut hull_query: Query<&mut Text, With<Hull>>,
+) {
+    let Ok(health) = player_query.get_single() else {
+        return;
+    };
+
+    for mut text in hull_query.iter_mut() {
+        text.sections[0].value = format!("Hull: {}/{}", health.current, health.max);
+    }
+}
+
+fn update_hyperspace_indicator(
+    player_query: Query<Ref<HyperspaceCooldown>, With<Player>>,
+    mut indicator_query: Query<&mut Text, With<HyperspaceIndicator>>,
+    bindings: Res<ActionBindings>,
+) {
+    let Ok(cooldown) = player_query.get_single() else {
+        return;
+    };
+    if !cooldown.is_changed() && !bindings.is_changed() {
+        return;
+    }
+
+    for mut text in indicator_query.iter_mut() {
+        text.sections[0].value = if cooldown.ready() {
+            format!("Hyperspace: Ready [{}]", bindings.hint(Action::Hyperspace))
+        } else {
+            format!("Hyperspace: {:.1}s", cooldown.remaining)
+        };
+    }
+}
@@ -0,0 +1,151 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use rand::Rng;
+
+use crate::{
+    schedules::InGameSet,
+    state::AppState,
+    player::{Player, Respawning, player_collider},
+    asteroids::Asteroid,
+    movement::{Velocity, Acceleration, play_field_size, clear_position, random_position},
+    collisions::Collider,
+    shield::PlayerShield,
+    health::{Health, Damage},
+    input::{Action, TickActions},
+    interpolation::PreviousTransform,
+    rng::{GameRng, RngStream},
+};
+
+pub struct HyperspacePlugin;
+
+impl Plugin for HyperspacePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HyperspaceConfig>();
+        app.add_systems(FixedUpdate, (
+            enter_hyperspace,
+        ).run_if(in_state(AppState::InGame)).in_set(InGameSet::UserInput));
+        app.add_systems(FixedUpdate, (
+            tick_hyperspace_cooldowns,
+            exit_hyperspace,
+        ).run_if(in_state(AppState::InGame)).in_set(InGameSet::EntityUpdates));
+    }
+}
+
+// How hyperspace jumps behave
+#[derive(Resource, Debug)]
+pub struct HyperspaceConfig {
+    // Seconds between jumps
+    pub cooldown: f32,
+    // How long the ship is gone for
+    pub delay: f32,
+    // Chance, from 0 to 1, that the ship blows up when it comes back
+    pub self_destruct_chance: f64,
+    // The ship tries to come back at least this far from every asteroid. 0 lets it land anywhere.
+    pub min_asteroid_distance: f32,
+}
+
+impl Default for HyperspaceConfig {
+    fn default() -> Self {
+        Self {
+            cooldown: 5.0,
+            delay: 0.5,
+            self_destruct_chance: 0.1,
+            min_asteroid_distance: 100.0,
+        }
+    }
+}
+
+// Time until the ship can jump again
+#[derive(Component, Debug, Default)]
+pub struct HyperspaceCooldown {
+    pub remaining: f32,
+}
+
+impl HyperspaceCooldown {
+    pub fn ready(&self) -> bool {
+        self.remaining <= 0.0
+    }
+}
+
+// The ship is in hyperspace and comes back out when the timer runs out
+#[derive(Component, Debug)]
+pub struct InHyperspace {
+    pub timer: Timer,
+}
+
+fn enter_hyperspace(
+    mut commands: Commands,
+    mut player_query: Query<
+        (Entity, &mut HyperspaceCooldown, &mut Velocity, &mut Acceleration, &mut Visibility),
+        (With<Player>, Without<Respawning>, Without<InHyperspace>),
+    >,
+    actions: Res<TickActions>,
+    config: Res<HyperspaceConfig>,
+) {
+    if !actions.just_pressed(Action::Hyperspace) {
+        return;
+    }
+    let Ok((entity, mut cooldown, mut velocity, mut acceleration, mut visibility)) = player_query.get_single_mut() else {
+        return;
+    };
+    if !cooldown.ready() {
+        return;
+    }
+
+    cooldown.remaining = config.cooldown;
+    velocity.value = Vec3::ZERO;
+    acceleration.value = Vec3::ZERO;
+    *visibility = Visibility::Hidden;
+    commands.entity(entity)
+        .remove::<(Collider, PlayerShield)>()
+        .insert(InHyperspace {
+            timer: Timer::from_seconds(config.delay, TimerMode::Once),
+        });
+}
+
+fn tick_hyperspace_cooldowns(mut query: Query<&mut HyperspaceCooldown>, time: Res<Time>) {
+    for mut cooldown in query.iter_mut() {
+        if !cooldown.ready() {
+            cooldown.remaining -= time.delta_seconds();
+        }
+    }
+}
+
+// Drops the ship back in at a random spot, unless the jump goes wrong
+fn exit_hyperspace(
+    mut commands: Commands,
+    mut player_query: Query<(Entity, &mut InHyperspace, &mut Transform, &mut Visibility, &Health)>,
+    asteroid_query: Query<&Transform, (With<Asteroid>, Without<InHyperspace>)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut damage_events: EventWriter<Damage>,
+    config: Res<HyperspaceConfig>,
+    mut rng: ResMut<GameRng>,
+    time: Res<Time>,
+) {
+    let window = window_query.get_single().unwrap();
+    let field = play_field_size(window);
+    let rng = rng.stream(RngStream::Hyperspace);
+
+    for (entity, mut hyperspace, mut transform, mut visibility, health) in player_query.iter_mut() {
+        if !hyperspace.timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+        commands.entity(entity).remove::<InHyperspace>();
+
+        let avoid: Vec<(Vec2, f32)> = if config.min_asteroid_distance > 0.0 {
+            asteroid_query.iter().map(|asteroid| (asteroid.translation.xy(), config.min_asteroid_distance)).collect()
+        } else {
+            Vec::new()
+        };
+        transform.translation = clear_position(rng, field, &avoid, |rng| random_position(rng, field))
+            .extend(transform.translation.z);
+        commands.entity(entity).insert(PreviousTransform(*transform));
+
+        if rng.gen_bool(config.self_destruct_chance.clamp(0.0, 1.0)) {
+            // The ship breaks up on re-entry, which costs a life like any other loss of hull
+            damage_events.send(Damage { target: entity, source: entity, amount: health.current });
+            continue;
+        }
+        *visibility = Visibility::Inherited;
+        commands.entity(entity).insert(player_collider());
+    }
+}
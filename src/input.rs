@@ -1,4 +1,26 @@
-use bevy::{prelude::*, input::{gamepad::GamepadEvent, keyboard::KeyboardInput}, window::CursorGrabMode};
+use bevy::{
+    prelude::*,
+    ecs::system::SystemParam,
+    input::{gamepad::GamepadEvent, keyboard::KeyboardInput, InputSystem},
+    utils::HashMap,
+    window::CursorGrabMode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    state::{AppState, PauseState},
+    settings::Settings,
+    storage::{config_dir, load_ron, save_ron},
+    schedules::TickInputSet,
+    despawn::reset_resource,
+    aim::AimTarget,
+};
+
+/// File the bindings are saved to, in the config directory
+const BINDINGS_FILE: &str = "controls.ron";
+
+/// Analog inputs count as pressed past this point
+const PRESS_THRESHOLD: f32 = 0.5;
 
 pub struct InputModeManagerPlugin;
 
@@ -6,13 +28,23 @@ impl Plugin for InputModeManagerPlugin {
     fn build(&self, app: &mut App) {
         // Add a state to record the current active input
         app.add_state::<ActiveInput>()
+            .insert_resource(ActionBindings::load())
+            .init_resource::<ActionState>()
+            .init_resource::<TickActions>()
+            // Actions are resolved once a frame, right after Bevy has read the devices
+            .add_systems(PreUpdate, resolve_actions.after(InputSystem))
+            // and handed to the simulation once a tick
+            .add_systems(FixedUpdate, sample_tick_input.run_if(in_state(AppState::InGame)).in_set(TickInputSet::Sample))
+            .add_systems(OnEnter(AppState::InGame), reset_resource::<TickActions>)
             // System to switch to gamepad as active input
             .add_systems(
                 Update, (
                 activate_gamepad.run_if(in_state(ActiveInput::MouseKeyboard)),
                 activate_mnk.run_if(in_state(ActiveInput::Gamepad)),
-                grab_mouse,
-            ));
+                grab_mouse.run_if(in_state(AppState::InGame).and_then(in_state(PauseState::Running))),
+            ))
+            .add_systems(OnEnter(PauseState::Paused), release_mouse)
+            .add_systems(OnExit(AppState::InGame), release_mouse);
     }
 }
 
@@ -23,6 +55,323 @@ pub enum ActiveInput {
     Gamepad,
 }
 
+/// How the ship is steered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ControlScheme {
+    /// Turn with the rotate actions
+    #[default]
+    Keyboard,
+    /// The ship turns toward the mouse cursor
+    MouseAim,
+}
+
+impl ControlScheme {
+    pub fn label(self) -> &'static str {
+        match self {
+            ControlScheme::Keyboard => "Keyboard",
+            ControlScheme::MouseAim => "Mouse aim",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            ControlScheme::Keyboard => ControlScheme::MouseAim,
+            ControlScheme::MouseAim => ControlScheme::Keyboard,
+        }
+    }
+}
+
+/// Everything the player can do, independent of the device it's done with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Thrust,
+    Reverse,
+    RotateLeft,
+    RotateRight,
+    Fire,
+    Shield,
+    Hyperspace,
+    CycleWeapon,
+    Pause,
+}
+
+impl Action {
+    pub const COUNT: usize = 9;
+    pub const ALL: [Action; Action::COUNT] = [
+        Action::Thrust,
+        Action::Reverse,
+        Action::RotateLeft,
+        Action::RotateRight,
+        Action::Fire,
+        Action::Shield,
+        Action::Hyperspace,
+        Action::CycleWeapon,
+        Action::Pause,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Thrust => "Thrust",
+            Action::Reverse => "Reverse",
+            Action::RotateLeft => "Rotate left",
+            Action::RotateRight => "Rotate right",
+            Action::Fire => "Fire",
+            Action::Shield => "Shield",
+            Action::Hyperspace => "Hyperspace",
+            Action::CycleWeapon => "Cycle weapon",
+            Action::Pause => "Pause",
+        }
+    }
+}
+
+/// A single input that can trigger an action
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// Triggers report how far they are pulled, other buttons are either 0 or 1
+    GamepadButton(GamepadButtonType),
+    /// One direction of a stick axis
+    GamepadAxis(GamepadAxisType, AxisDirection),
+}
+
+impl Binding {
+    pub fn label(self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => format!("Mouse {:?}", button),
+            Binding::GamepadButton(button) => format!("Pad {:?}", button),
+            Binding::GamepadAxis(axis, direction) => {
+                let sign = match direction {
+                    AxisDirection::Positive => "+",
+                    AxisDirection::Negative => "-",
+                };
+                format!("Pad {:?}{}", axis, sign)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AxisDirection {
+    Positive,
+    Negative,
+}
+
+/// The inputs bound to each action. Any of them triggers it.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct ActionBindings {
+    pub bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl ActionBindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], |bindings| bindings.as_slice())
+    }
+
+    /// The action already using `binding`, if any
+    pub fn action_for(&self, binding: Binding) -> Option<Action> {
+        Action::ALL.into_iter().find(|&action| self.get(action).contains(&binding))
+    }
+
+    /// Short text naming the first input bound to an action, for on-screen hints
+    pub fn hint(&self, action: Action) -> String {
+        self.get(action).first().map_or_else(|| "Unbound".to_string(), |binding| binding.label())
+    }
+
+    /// The saved bindings, or the defaults if there aren't any or they can't be read. Actions
+    /// missing from the file keep their default bindings.
+    pub fn load() -> Self {
+        let mut bindings = Self::default();
+        if let Some(saved) = config_dir().and_then(|dir| load_ron::<ActionBindings>(&dir.join(BINDINGS_FILE))) {
+            bindings.bindings.extend(saved.bindings);
+        }
+        bindings
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let dir = config_dir().ok_or_else(|| "no config directory".to_string())?;
+        save_ron(&dir.join(BINDINGS_FILE), self)
+    }
+}
+
+impl Default for ActionBindings {
+    fn default() -> Self {
+        use Binding::*;
+        use AxisDirection::*;
+
+        let bindings = HashMap::from_iter([
+            (Action::Thrust, vec![
+                Key(KeyCode::W), Key(KeyCode::Up), Mouse(MouseButton::Right),
+                GamepadAxis(GamepadAxisType::LeftStickY, Positive), GamepadButton(GamepadButtonType::RightTrigger2),
+            ]),
+            (Action::Reverse, vec![
+                Key(KeyCode::S), Key(KeyCode::Down),
+                GamepadAxis(GamepadAxisType::LeftStickY, Negative), GamepadButton(GamepadButtonType::LeftTrigger2),
+            ]),
+            (Action::RotateLeft, vec![
+                Key(KeyCode::A), Key(KeyCode::Left),
+                GamepadAxis(GamepadAxisType::LeftStickX, Negative), GamepadButton(GamepadButtonType::DPadLeft),
+            ]),
+            (Action::RotateRight, vec![
+                Key(KeyCode::D), Key(KeyCode::Right),
+                GamepadAxis(GamepadAxisType::LeftStickX, Positive), GamepadButton(GamepadButtonType::DPadRight),
+            ]),
+            (Action::Fire, vec![Key(KeyCode::Space), Mouse(MouseButton::Left), GamepadButton(GamepadButtonType::South)]),
+            (Action::Shield, vec![Key(KeyCode::Tab), GamepadButton(GamepadButtonType::West)]),
+            (Action::Hyperspace, vec![Key(KeyCode::ShiftLeft), Key(KeyCode::ShiftRight), GamepadButton(GamepadButtonType::North)]),
+            (Action::CycleWeapon, vec![Key(KeyCode::Q), GamepadButton(GamepadButtonType::RightTrigger)]),
+            (Action::Pause, vec![Key(KeyCode::Escape), GamepadButton(GamepadButtonType::Start)]),
+        ]);
+        Self { bindings }
+    }
+}
+
+/// How strongly each action is held this frame, from 0 to 1, and whether it was held last frame
+#[derive(Resource, Debug, Default)]
+pub struct ActionState {
+    values: HashMap<Action, f32>,
+    previous: HashMap<Action, f32>,
+}
+
+impl ActionState {
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or(0.0)
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.value(action) >= PRESS_THRESHOLD
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed(action) && self.previous.get(&action).copied().unwrap_or(0.0) < PRESS_THRESHOLD
+    }
+}
+
+/// Everything the player did on one simulation tick. This is all the input the simulation sees,
+/// which is what lets a run be recorded and played back exactly.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TickInput {
+    /// How strongly each action is held, indexed like `Action::ALL`, from 0 to 255
+    pub actions: [u8; Action::COUNT],
+    /// The point mouse aim steers toward, in whole world units
+    pub aim: Option<IVec2>,
+}
+
+impl TickInput {
+    pub fn new(actions: &ActionState, aim: Option<Vec2>) -> Self {
+        Self {
+            actions: Action::ALL.map(|action| (actions.value(action) * 255.0).round() as u8),
+            aim: aim.map(|aim| aim.round().as_ivec2()),
+        }
+    }
+}
+
+/// The actions as the simulation sees them. Menus read `ActionState`, which changes every frame,
+/// while this changes once a tick, so a press is just pressed for exactly one tick however many
+/// frames or ticks it lasts.
+#[derive(Resource, Debug, Default)]
+pub struct TickActions {
+    pub current: TickInput,
+    previous: TickInput,
+}
+
+impl TickActions {
+    pub fn advance(&mut self, input: TickInput) {
+        self.previous = self.current;
+        self.current = input;
+    }
+
+    pub fn value(&self, action: Action) -> f32 {
+        self.current.actions[action as usize] as f32 / 255.0
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.value(action) >= PRESS_THRESHOLD
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed(action) && (self.previous.actions[action as usize] as f32 / 255.0) < PRESS_THRESHOLD
+    }
+
+    /// Where the ship should turn toward, if it's steered with the mouse
+    pub fn aim(&self) -> Option<Vec2> {
+        self.current.aim.map(|aim| aim.as_vec2())
+    }
+}
+
+/// Every device an action can be bound to
+#[derive(SystemParam)]
+pub struct InputSources<'w> {
+    keys: Res<'w, Input<KeyCode>>,
+    mouse: Res<'w, Input<MouseButton>>,
+    gamepads: Res<'w, Gamepads>,
+    gamepad_buttons: Res<'w, Input<GamepadButton>>,
+    gamepad_button_axes: Res<'w, Axis<GamepadButton>>,
+    gamepad_axes: Res<'w, Axis<GamepadAxis>>,
+}
+
+impl InputSources<'_> {
+    /// How strongly a binding is held, taking the strongest of all connected gamepads
+    pub fn value(&self, binding: Binding) -> f32 {
+        match binding {
+            Binding::Key(key) => pressed_value(self.keys.pressed(key)),
+            Binding::Mouse(button) => pressed_value(self.mouse.pressed(button)),
+            Binding::GamepadButton(button_type) => self.gamepads.iter()
+                .map(|gamepad| {
+                    let button = GamepadButton::new(gamepad, button_type);
+                    self.gamepad_button_axes.get(button)
+                        .unwrap_or_else(|| pressed_value(self.gamepad_buttons.pressed(button)))
+                })
+                .fold(0.0, f32::max),
+            Binding::GamepadAxis(axis_type, direction) => self.gamepads.iter()
+                .map(|gamepad| {
+                    let value = self.gamepad_axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.0);
+                    match direction {
+                        AxisDirection::Positive => value.max(0.0),
+                        AxisDirection::Negative => (-value).max(0.0),
+                    }
+                })
+                .fold(0.0, f32::max),
+        }
+    }
+}
+
+fn pressed_value(pressed: bool) -> f32 {
+    if pressed { 1.0 } else { 0.0 }
+}
+
+fn resolve_actions(
+    mut action_state: ResMut<ActionState>,
+    bindings: Res<ActionBindings>,
+    sources: InputSources,
+) {
+    let state = &mut *action_state;
+    std::mem::swap(&mut state.values, &mut state.previous);
+    state.values.clear();
+
+    for action in Action::ALL {
+        let value = bindings.get(action).iter()
+            .map(|&binding| sources.value(binding))
+            .fold(0.0, f32::max);
+        state.values.insert(action, value.clamp(0.0, 1.0));
+    }
+}
+
+/// Hands this frame's actions to the simulation for the coming tick
+fn sample_tick_input(
+    mut tick_actions: ResMut<TickActions>,
+    actions: Res<ActionState>,
+    aim_target: Res<AimTarget>,
+    settings: Res<Settings>,
+) {
+    let aim = match settings.control_scheme {
+        ControlScheme::Keyboard => None,
+        ControlScheme::MouseAim => aim_target.0,
+    };
+    tick_actions.advance(TickInput::new(&actions, aim));
+}
+
 /// Switch the gamepad when any button is pressed or any axis input used
 fn activate_gamepad(
     mut next_state: ResMut<NextState<ActiveInput>>,
@@ -51,16 +400,23 @@ fn activate_mnk(
     }
 }
 
-fn grab_mouse(mut windows: Query<&mut Window>, mouse: Res<Input<MouseButton>>, key: Res<Input<KeyCode>>,) {
+/// Locks the cursor to the window once the player clicks into a running game. Mouse aim needs the
+/// cursor to keep moving, so it is only kept inside the window and drawn as a crosshair instead.
+fn grab_mouse(mut windows: Query<&mut Window>, mouse: Res<Input<MouseButton>>, settings: Res<Settings>) {
     let mut window = windows.single_mut();
 
     if mouse.just_pressed(MouseButton::Left) {
         window.cursor.visible = false;
-        window.cursor.grab_mode = CursorGrabMode::Locked;
+        window.cursor.grab_mode = match settings.control_scheme {
+            ControlScheme::Keyboard => CursorGrabMode::Locked,
+            ControlScheme::MouseAim => CursorGrabMode::Confined,
+        };
     }
+}
 
-    if key.just_pressed(KeyCode::Escape) {
-        window.cursor.visible = true;
-        window.cursor.grab_mode = CursorGrabMode::None;
-    }
+/// Menus need the cursor back
+fn release_mouse(mut windows: Query<&mut Window>) {
+    let mut window = windows.single_mut();
+    window.cursor.visible = true;
+    window.cursor.grab_mode = CursorGrabMode::None;
 }
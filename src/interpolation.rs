@@ -0,0 +1,87 @@
+use bevy::{prelude::*, transform::TransformSystem, window::PrimaryWindow};
+
+use crate::{
+    state::AppState,
+    schedules::InGameSet,
+    movement::{Velocity, WrapGhost, play_field_size, wrapped_offset},
+};
+
+pub struct InterpolationPlugin;
+
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, save_previous_transforms
+                .run_if(in_state(AppState::InGame))
+                .before(InGameSet::DespawnEntities))
+            .add_systems(PostUpdate, interpolate_transforms
+                .run_if(in_state(AppState::InGame))
+                .after(TransformSystem::TransformPropagate));
+    }
+}
+
+// Where an entity was at the end of the tick before the last one. Frames usually fall between
+// ticks, so moving things are drawn part way from here to their `Transform`. Inserting it with the
+// current transform makes the entity jump straight there instead, like after a teleport.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PreviousTransform(pub Transform);
+
+// Runs before every tick, including the ones where the simulation is paused, so a paused game is
+// drawn where it actually is
+fn save_previous_transforms(
+    mut commands: Commands,
+    mut query: Query<(Entity, &Transform, Option<&mut PreviousTransform>), With<Velocity>>,
+) {
+    for (entity, transform, previous) in query.iter_mut() {
+        match previous {
+            Some(mut previous) => previous.0 = *transform,
+            None => {
+                commands.entity(entity).insert(PreviousTransform(*transform));
+            }
+        }
+    }
+}
+
+// Draws everything as it was part way through the tick currently in progress. Only what's drawn
+// changes, the simulation keeps its `Transform`s.
+fn interpolate_transforms(
+    mut query: Query<(&PreviousTransform, &Transform, &mut GlobalTransform, Option<&Children>)>,
+    mut child_query: Query<(&Transform, &mut GlobalTransform), (Without<PreviousTransform>, Without<WrapGhost>)>,
+    mut ghost_query: Query<(&WrapGhost, &Transform, &mut GlobalTransform), Without<PreviousTransform>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let window = window_query.get_single().unwrap();
+    let field = play_field_size(window);
+    let alpha = fixed_time.overstep_percentage();
+
+    for (previous, current, mut global_transform, children) in query.iter_mut() {
+        // Something that just wrapped around moved a short way across the edge, not all the way
+        // across the play field
+        let offset = wrapped_offset(previous.0.translation.xy(), current.translation.xy(), field);
+        let translation = current.translation - (offset * (1.0 - alpha)).extend(0.0);
+        let transform = Transform {
+            translation,
+            rotation: previous.0.rotation.slerp(current.rotation, alpha),
+            scale: previous.0.scale.lerp(current.scale, alpha),
+        };
+        *global_transform = GlobalTransform::from(transform);
+
+        // Children, like the shield bubble, follow the interpolated parent
+        for &child in children.into_iter().flatten() {
+            if let Ok((child_transform, mut child_global_transform)) = child_query.get_mut(child) {
+                *child_global_transform = global_transform.mul_transform(*child_transform);
+            }
+        }
+    }
+
+    // A ghost jumps a whole play field whenever its source crosses the middle, so it can't be
+    // interpolated on its own. It's drawn the same distance from the interpolated source as it is
+    // from the source's `Transform` instead, which also makes it follow teleports.
+    for (ghost, ghost_transform, mut ghost_global_transform) in ghost_query.iter_mut() {
+        let Ok((_, source_transform, source_global_transform, _)) = query.get(ghost.source) else {
+            continue;
+        };
+        let offset = ghost_transform.translation - source_transform.translation;
+        *ghost_global_transform = GlobalTransform::from_translation(offset) * *source_global_transform;
+    }
+}
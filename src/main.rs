@@ -1,4 +1,10 @@
-// mod input;
+// This lint usually gives bad advice in the context of Bevy -- hiding complex queries behind
+// type aliases tends to obfuscate code while offering no improvement in code cleanliness.
+#![allow(clippy::type_complexity)]
+// Systems take their resources and queries as arguments, so they grow past clippy's limit quickly
+#![allow(clippy::too_many_arguments)]
+
+mod input;
 mod player;
 mod asteroids;
 mod collisions;
@@ -9,16 +15,51 @@ mod asset_loader;
 mod state;
 mod menu;
 mod hud;
+mod game_over;
+mod pause;
+mod settings;
+mod spatial_hash;
+mod storage;
+mod controls;
+mod shield;
+mod weapon;
+mod bullets;
+mod health;
+mod hyperspace;
+mod aim;
+mod rng;
+mod replay;
+mod interpolation;
+mod scores;
+mod daily;
+mod high_scores;
 
 use asset_loader::AssetLoaderPlugin;
 use asteroids::AsteroidPlugin;
 use bevy::{prelude::*, window::PrimaryWindow};
 use collisions::CollisionDetectionPlugin;
 use despawn::DespawnPlugin;
+use game_over::GameOverPlugin;
 use hud::HudPlugin;
+use input::InputModeManagerPlugin;
 use menu::MenuPlugin;
 use movement::MovementPlugin;
+use pause::PausePlugin;
 use player::PlayerPlugin;
+use schedules::SchedulePlugin;
+use settings::SettingsPlugin;
+use controls::ControlsPlugin;
+use shield::ShieldPlugin;
+use weapon::WeaponPlugin;
+use bullets::BulletsPlugin;
+use health::HealthPlugin;
+use hyperspace::HyperspacePlugin;
+use aim::AimPlugin;
+use rng::RngPlugin;
+use replay::ReplayPlugin;
+use interpolation::InterpolationPlugin;
+use daily::DailyPlugin;
+use high_scores::HighScoresPlugin;
 use state::AppState;
 
 fn main() {
@@ -28,6 +69,8 @@ fn main() {
         .add_state::<AppState>()
 
         // Custom Plugins
+        .add_plugins(SchedulePlugin)
+        .add_plugins(InputModeManagerPlugin)
         .add_plugins(AssetLoaderPlugin)
         .add_plugins(MovementPlugin)
         .add_plugins(PlayerPlugin)
@@ -36,8 +79,24 @@ fn main() {
         .add_plugins(DespawnPlugin)
         .add_plugins(MenuPlugin)
         .add_plugins(HudPlugin)
+        .add_plugins(GameOverPlugin)
+        .add_plugins(PausePlugin)
+        .add_plugins(SettingsPlugin)
+        .add_plugins(ControlsPlugin)
+        .add_plugins(ShieldPlugin)
+        .add_plugins(WeaponPlugin)
+        .add_plugins(BulletsPlugin)
+        .add_plugins(HealthPlugin)
+        .add_plugins(HyperspacePlugin)
+        .add_plugins(AimPlugin)
+        .add_plugins(RngPlugin)
+        .add_plugins(ReplayPlugin)
+        .add_plugins(InterpolationPlugin)
+        .add_plugins(DailyPlugin)
+        .add_plugins(HighScoresPlugin)
 
         .add_systems(Startup, setup)
+        .add_systems(Update, center_camera)
 
         .run();
 }
@@ -53,3 +112,21 @@ fn setup(mut commands: Commands, window_query: Query<&Window, With<PrimaryWindow
     }, MainCamera));
 }
 
+// The play field is as big as the window, so the camera stays on its middle when the window is
+// resized, like when a replay puts it back to the size it was recorded at
+fn center_camera(
+    window_query: Query<&Window, (With<PrimaryWindow>, Changed<Window>)>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>,
+) {
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    let center = Vec2::new(window.width() / 2.0, window.height() / 2.0);
+
+    for mut transform in camera_query.iter_mut() {
+        // Only write when needed so change detection isn't triggered every time the window changes
+        if transform.translation.xy() != center {
+            transform.translation = center.extend(transform.translation.z);
+        }
+    }
+}
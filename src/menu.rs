@@ -9,9 +9,9 @@
 // type aliases tends to obfuscate code while offering no improvement in code cleanliness.
 
 
-use bevy::prelude::*;
+use bevy::{prelude::*, window::PrimaryWindow};
 
-use crate::state::AppState;
+use crate::{state::AppState, asset_loader::SceneAssets, replay::{latest_replay, watch_replay}, daily::{RunMode, Date}};
 
 pub struct MenuPlugin;
 
@@ -32,72 +32,142 @@ impl Plugin for MenuPlugin {
 
 #[derive(Resource)]
 struct MenuData {
-    btn_start: Entity,
+    screen: Entity,
 }
 
 #[derive(Component)]
 struct MainMenu;
 
-const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
-const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
-const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
+#[derive(Component)]
+enum MenuButton {
+    Start,
+    DailyChallenge,
+    HighScores,
+    WatchReplay,
+}
+
+pub const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+pub const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
+pub const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
+pub const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+
+// Spawns a labelled button for the in-game screens. `marker` tells the screen's system which
+// button was pressed.
+pub fn spawn_button(parent: &mut ChildBuilder, font: &Handle<Font>, label: &str, marker: impl Component) -> Entity {
+    parent
+        .spawn((ButtonBundle {
+            style: Style {
+                // Grows past this to fit longer labels
+                min_width: Val::Px(260.),
+                height: Val::Px(65.),
+                padding: UiRect::horizontal(Val::Px(16.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: NORMAL_BUTTON.into(),
+            ..default()
+        }, marker))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 28.0,
+                    color: TEXT_COLOR,
+                },
+            ));
+        })
+        .id()
+}
+
+// A compact button for screens with many of them, like one per row of a list
+pub fn spawn_small_button(parent: &mut ChildBuilder, font: &Handle<Font>, label: &str, marker: impl Component) -> Entity {
+    parent
+        .spawn((ButtonBundle {
+            style: Style {
+                min_width: Val::Px(80.),
+                height: Val::Px(32.),
+                padding: UiRect::horizontal(Val::Px(8.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: NORMAL_BUTTON.into(),
+            ..default()
+        }, marker))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 18.0,
+                    color: TEXT_COLOR,
+                },
+            ));
+        })
+        .id()
+}
 
-fn setup_menu(mut commands: Commands) {
+fn setup_menu(mut commands: Commands, scene_assets: Res<SceneAssets>) {
     println!("Started");
-    let btn_start = commands
+    let font = scene_assets.font.clone();
+    let screen = commands
         .spawn((NodeBundle {
             style: Style {
-                // center button
+                // center buttons
                 width: Val::Percent(100.),
                 height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
                 justify_content: JustifyContent::Center,
                 align_items: AlignItems::Center,
+                row_gap: Val::Px(12.),
                 ..default()
             },
             ..default()
         }, MainMenu))
         .with_children(|parent| {
-            parent
-                .spawn(ButtonBundle {
-                    style: Style {
-                        width: Val::Px(150.),
-                        height: Val::Px(65.),
-                        // horizontally center child text
-                        justify_content: JustifyContent::Center,
-                        // vertically center child text
-                        align_items: AlignItems::Center,
-                        ..default()
-                    },
-                    background_color: NORMAL_BUTTON.into(),
-                    ..default()
-                })
-                .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section(
-                        "Start",
-                        TextStyle {
-                            font_size: 40.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
-                            ..default()
-                        },
-                    ));
-                });
+            spawn_button(parent, &font, "Start", MenuButton::Start);
+            spawn_button(parent, &font, "Daily challenge", MenuButton::DailyChallenge);
+            spawn_button(parent, &font, "High scores", MenuButton::HighScores);
+            spawn_button(parent, &font, "Watch last run", MenuButton::WatchReplay);
         })
         .id();
-    commands.insert_resource(MenuData { btn_start });
+    commands.insert_resource(MenuData { screen });
 }
 
 fn menu(
+    mut commands: Commands,
     mut next_state: ResMut<NextState<AppState>>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
     mut interaction_query: Query<
-        (&Interaction, &mut BackgroundColor),
-        (Changed<Interaction>, With<Button>),
+        (&Interaction, &mut BackgroundColor, &MenuButton),
+        Changed<Interaction>,
     >,
 ) {
-    for (interaction, mut color) in &mut interaction_query {
+    for (interaction, mut color, button) in &mut interaction_query {
         match *interaction {
             Interaction::Pressed => {
                 *color = PRESSED_BUTTON.into();
-                next_state.set(AppState::InGame);
+                match button {
+                    MenuButton::Start => {
+                        commands.insert_resource(RunMode::Standard);
+                        next_state.set(AppState::InGame);
+                    }
+                    MenuButton::DailyChallenge => {
+                        commands.insert_resource(RunMode::Daily(Date::today()));
+                        next_state.set(AppState::InGame);
+                    }
+                    MenuButton::HighScores => next_state.set(AppState::HighScores),
+                    MenuButton::WatchReplay => {
+                        let Some(replay) = latest_replay() else {
+                            info!("There's no replay to watch yet");
+                            continue;
+                        };
+                        watch_replay(&mut commands, &mut window_query.single_mut(), replay);
+                        next_state.set(AppState::InGame);
+                    }
+                }
             }
             Interaction::Hovered => {
                 *color = HOVERED_BUTTON.into();
@@ -110,6 +180,5 @@ fn menu(
 }
 
 fn cleanup_menu(mut commands: Commands, menu: Res<MenuData>) {
-    commands.entity(menu.btn_start).despawn_recursive();
+    commands.entity(menu.screen).despawn_recursive();
 }
-
@@ -1,6 +1,7 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, window::PrimaryWindow};
+use rand::Rng;
 
-use crate::{collisions::Collider, schedules::InGameSet, player::Player, state::AppState};
+use crate::{collisions::Collider, schedules::InGameSet, player::Player, state::AppState, despawn::InGameScoped, input::{Action, TickActions}};
 
 #[derive(Component, Debug)]
 pub struct Velocity {
@@ -13,6 +14,7 @@ impl Velocity {
     }
 }
 
+// Change in velocity per second
 #[derive(Component, Debug)]
 pub struct Acceleration {
     pub value: Vec3,
@@ -24,6 +26,30 @@ impl Acceleration {
     }
 }
 
+// Marks an entity that leaves one edge of the play field and comes back on the opposite edge
+#[derive(Component, Debug)]
+pub struct Wraps;
+
+// A copy of a wrapping entity's sprite, drawn on the other side of the play field so the entity
+// stays visible while it overlaps an edge. It's hidden the rest of the time.
+#[derive(Component, Debug)]
+pub struct WrapGhost {
+    pub source: Entity,
+    // Which axes this ghost is mirrored across: (1, 0), (0, 1) or (1, 1)
+    pub axes: Vec2,
+}
+
+// Put on a wrapping entity once its ghosts have been spawned
+#[derive(Component, Debug)]
+struct HasWrapGhosts;
+
+const GHOST_AXES: [Vec2; 3] = [Vec2::X, Vec2::Y, Vec2::ONE];
+// How many random points are tried when looking for one clear of everything to avoid
+const PLACEMENT_ATTEMPTS: usize = 16;
+// How quickly a coasting ship slows down, as a rate per second compounded continuously, so it slows
+// the same however long a tick is
+const COASTING_DRAG: f32 = 0.9;
+
 #[derive(Bundle)]
 pub struct MovingObjectBundle {
     pub velocity: Velocity,
@@ -36,32 +62,216 @@ pub struct MovementPlugin;
 
 impl Plugin for MovementPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (
+        app.add_systems(FixedUpdate, (
             update_velocity, 
-            update_position
+            update_position,
+            wrap_position,
+            spawn_wrap_ghosts,
+            update_wrap_ghosts,
         ).run_if(in_state(AppState::InGame))
         .chain().in_set(InGameSet::EntityUpdates)); 
     }
 }
 
-fn update_position(mut query: Query<(&mut Velocity, &mut Transform, Has<Player>)>, time: Res<Time>, keyboard_input: Res<Input<KeyCode>>) {
-    // If entity has Player tag, do stuff
+// Size of the play field, which is the window the camera is centered on
+pub fn play_field_size(window: &Window) -> Vec2 {
+    Vec2::new(window.width(), window.height())
+}
+
+// Picks a point with `candidate` that is at least the given distance from every point in `avoid`.
+// If none turns up after a few tries, the point that intrudes the least is used.
+pub fn clear_position<R: Rng>(rng: &mut R, field: Vec2, avoid: &[(Vec2, f32)], mut candidate: impl FnMut(&mut R) -> Vec2) -> Vec2 {
+    let mut best = Vec2::ZERO;
+    let mut best_clearance = f32::NEG_INFINITY;
+
+    for _ in 0..PLACEMENT_ATTEMPTS {
+        let point = candidate(rng);
+        // How far the point is outside the closest thing's radius
+        let clearance = avoid.iter()
+            .map(|&(position, distance)| wrapped_offset(position, point, field).length() - distance)
+            .fold(f32::INFINITY, f32::min);
+
+        if clearance >= 0.0 {
+            return point;
+        }
+        if clearance > best_clearance {
+            best = point;
+            best_clearance = clearance;
+        }
+    }
+    best
+}
+
+// Any point in the play field
+pub fn random_position(rng: &mut impl Rng, field: Vec2) -> Vec2 {
+    Vec2::new(rng.gen_range(0.0..field.x), rng.gen_range(0.0..field.y))
+}
+
+// Shortest offset from `from` to `to` when the play field wraps around at its edges
+pub fn wrapped_offset(from: Vec2, to: Vec2, field: Vec2) -> Vec2 {
+    let mut offset = to - from;
+    if offset.x > field.x / 2.0 {
+        offset.x -= field.x;
+    } else if offset.x < -field.x / 2.0 {
+        offset.x += field.x;
+    }
+    if offset.y > field.y / 2.0 {
+        offset.y -= field.y;
+    } else if offset.y < -field.y / 2.0 {
+        offset.y += field.y;
+    }
+    offset
+}
+
+// Where a ghost should be drawn: mirrored towards the half of the play field the source is not in
+fn ghost_translation(source: Vec3, axes: Vec2, field: Vec2) -> Vec3 {
+    let x = if source.x < field.x / 2.0 { field.x } else { -field.x };
+    let y = if source.y < field.y / 2.0 { field.y } else { -field.y };
+    source + Vec3::new(x * axes.x, y * axes.y, 0.0)
+}
+
+// Whether something this big at `position` pokes out past the edges a ghost on `axes` mirrors
+fn overlaps_edge(position: Vec3, radius: f32, axes: Vec2, field: Vec2) -> bool {
+    let near_x = position.x < radius || position.x > field.x - radius;
+    let near_y = position.y < radius || position.y > field.y - radius;
+    (axes.x == 0.0 || near_x) && (axes.y == 0.0 || near_y)
+}
+
+fn update_position(mut query: Query<(&mut Velocity, &mut Transform, Has<Player>)>, time: Res<Time>, actions: Res<TickActions>) {
+    // The ship drifts to a stop when it isn't thrusting either way
+    let coasting = actions.value(Action::Thrust) == 0.0 && actions.value(Action::Reverse) == 0.0;
 
     for (mut velocity, mut transform, has_player) in query.iter_mut() {
-        if !keyboard_input.pressed(KeyCode::W) && !keyboard_input.pressed(KeyCode::Up) && !keyboard_input.pressed(KeyCode::S) && !keyboard_input.pressed(KeyCode::Down) 
-            && has_player {
-            velocity.value *= 1.0 - (0.9 * time.delta_seconds());  
-        } 
+        if coasting && has_player {
+            velocity.value *= (-COASTING_DRAG * time.delta_seconds()).exp();
+        }
         transform.translation += velocity.value * time.delta_seconds();
     }
 }
 
-fn update_velocity(mut query: Query<(&mut Acceleration, &mut Velocity)>) {
+fn update_velocity(mut query: Query<(&Acceleration, &mut Velocity)>, time: Res<Time>) {
     for (acceleration, mut velocity) in query.iter_mut() {
-        // acceleration.value = Vec3::new(0.1, 0.0, 0.0);
-        velocity.value += acceleration.value;
-        // println!("{}", acceleration.value);
-        // acceleration.value = Vec3::ZERO;
+        velocity.value += acceleration.value * time.delta_seconds();
     }
 }
 
+fn wrap_position(mut query: Query<&mut Transform, With<Wraps>>, window_query: Query<&Window, With<PrimaryWindow>>) {
+    let window = window_query.get_single().unwrap();
+    let field = play_field_size(window);
+
+    for mut transform in query.iter_mut() {
+        let translation = transform.translation;
+        // Only write when needed so change detection isn't triggered every tick
+        if translation.x < 0.0 || translation.x >= field.x || translation.y < 0.0 || translation.y >= field.y {
+            transform.translation.x = translation.x.rem_euclid(field.x);
+            transform.translation.y = translation.y.rem_euclid(field.y);
+        }
+    }
+}
+
+// Ghosts are only spawned the first time an entity reaches an edge, so the many that never do, like
+// most bullets, don't get any
+fn spawn_wrap_ghosts(
+    mut commands: Commands,
+    query: Query<(Entity, &Transform, &Sprite, &Handle<Image>, &Collider), (With<Wraps>, Without<HasWrapGhosts>)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
+    let window = window_query.get_single().unwrap();
+    let field = play_field_size(window);
+
+    for (entity, transform, sprite, texture, collider) in query.iter() {
+        let at_edge = overlaps_edge(transform.translation, collider.radius, Vec2::X, field)
+            || overlaps_edge(transform.translation, collider.radius, Vec2::Y, field);
+        if !at_edge {
+            continue;
+        }
+
+        commands.entity(entity).insert(HasWrapGhosts);
+        for axes in GHOST_AXES {
+            commands.spawn((SpriteBundle {
+                transform: transform.with_translation(ghost_translation(transform.translation, axes, field)),
+                sprite: sprite.clone(),
+                texture: texture.clone(),
+                visibility: Visibility::Hidden,
+                ..default()
+            }, WrapGhost { source: entity, axes }, InGameScoped));
+        }
+    }
+}
+
+fn update_wrap_ghosts(
+    mut commands: Commands,
+    mut ghost_query: Query<(Entity, &WrapGhost, &mut Transform, &mut Sprite, &mut Visibility)>,
+    source_query: Query<(&Transform, &Sprite, &Visibility, Option<&Collider>), (With<Wraps>, Without<WrapGhost>)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
+    let window = window_query.get_single().unwrap();
+    let field = play_field_size(window);
+
+    for (entity, ghost, mut transform, mut sprite, mut visibility) in ghost_query.iter_mut() {
+        // The entity this ghost mirrors is gone, so the ghost goes too
+        let Ok((source_transform, source_sprite, source_visibility, collider)) = source_query.get(ghost.source) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        // A ship waiting to respawn has no collider, and its ghosts wait with it
+        let shown = *source_visibility != Visibility::Hidden
+            && collider.is_some_and(|collider| overlaps_edge(source_transform.translation, collider.radius, ghost.axes, field));
+        let target_visibility = if shown { *source_visibility } else { Visibility::Hidden };
+        // Only write when needed so change detection isn't triggered every tick
+        if *visibility != target_visibility {
+            *visibility = target_visibility;
+        }
+        // Nothing to keep up to date while nobody can see it
+        if !shown {
+            continue;
+        }
+
+        *transform = source_transform.with_translation(ghost_translation(source_transform.translation, ghost.axes, field));
+        sprite.color = source_sprite.color;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELD: Vec2 = Vec2::new(800.0, 600.0);
+
+    #[test]
+    fn offsets_take_the_short_way_across_the_seam() {
+        assert_eq!(wrapped_offset(Vec2::new(790.0, 300.0), Vec2::new(10.0, 300.0), FIELD), Vec2::new(20.0, 0.0));
+        assert_eq!(wrapped_offset(Vec2::new(400.0, 5.0), Vec2::new(400.0, 595.0), FIELD), Vec2::new(0.0, -10.0));
+        assert_eq!(wrapped_offset(Vec2::new(5.0, 5.0), Vec2::new(795.0, 595.0), FIELD), Vec2::new(-10.0, -10.0));
+        // Nothing to wrap when the straight way is shorter
+        assert_eq!(wrapped_offset(Vec2::new(100.0, 100.0), Vec2::new(200.0, 150.0), FIELD), Vec2::new(100.0, 50.0));
+    }
+
+    #[test]
+    fn ghosts_mirror_into_the_other_half() {
+        let near_origin = Vec3::new(10.0, 20.0, 0.0);
+        assert_eq!(ghost_translation(near_origin, Vec2::X, FIELD), Vec3::new(810.0, 20.0, 0.0));
+        assert_eq!(ghost_translation(near_origin, Vec2::Y, FIELD), Vec3::new(10.0, 620.0, 0.0));
+        assert_eq!(ghost_translation(near_origin, Vec2::ONE, FIELD), Vec3::new(810.0, 620.0, 0.0));
+
+        let far_corner = Vec3::new(790.0, 590.0, 0.0);
+        assert_eq!(ghost_translation(far_corner, Vec2::ONE, FIELD), Vec3::new(-10.0, -10.0, 0.0));
+    }
+
+    #[test]
+    fn corner_ghosts_need_both_edges() {
+        let corner = Vec3::new(5.0, 595.0, 0.0);
+        for axes in GHOST_AXES {
+            assert!(overlaps_edge(corner, 10.0, axes, FIELD));
+        }
+
+        let left_edge = Vec3::new(5.0, 300.0, 0.0);
+        assert!(overlaps_edge(left_edge, 10.0, Vec2::X, FIELD));
+        assert!(!overlaps_edge(left_edge, 10.0, Vec2::Y, FIELD));
+        assert!(!overlaps_edge(left_edge, 10.0, Vec2::ONE, FIELD));
+
+        let middle = Vec3::new(400.0, 300.0, 0.0);
+        assert!(GHOST_AXES.iter().all(|&axes| !overlaps_edge(middle, 10.0, axes, FIELD)));
+    }
+}
@@ -0,0 +1,149 @@
+use bevy::{prelude::*, window::WindowFocused};
+
+use crate::{
+    state::{AppState, PauseState},
+    asset_loader::SceneAssets,
+    settings::{Settings, SettingsState},
+    input::{Action, ActionState},
+    menu::{NORMAL_BUTTON, HOVERED_BUTTON, PRESSED_BUTTON, TEXT_COLOR, spawn_button},
+};
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<PauseState>()
+            .add_systems(Update, (
+                // Escape closes the settings screen first when it is open
+                toggle_pause.run_if(in_state(SettingsState::Closed)),
+                pause_on_focus_loss,
+            ).run_if(in_state(AppState::InGame)))
+            .add_systems(OnEnter(PauseState::Paused), setup_pause_menu)
+            .add_systems(Update, pause_menu.run_if(in_state(PauseState::Paused)))
+            .add_systems(OnExit(PauseState::Paused), cleanup_pause_menu)
+            // However a run ends, the next one starts unpaused
+            .add_systems(OnExit(AppState::InGame), resume)
+            .add_systems(OnEnter(AppState::Restarting), restart_run);
+    }
+}
+
+#[derive(Resource)]
+struct PauseMenuData {
+    screen: Entity,
+}
+
+#[derive(Component)]
+enum PauseButton {
+    Resume,
+    Restart,
+    Settings,
+    QuitToMenu,
+}
+
+fn toggle_pause(
+    actions: Res<ActionState>,
+    state: Res<State<PauseState>>,
+    mut next_state: ResMut<NextState<PauseState>>,
+) {
+    if !actions.just_pressed(Action::Pause) {
+        return;
+    }
+    match state.get() {
+        PauseState::Running => next_state.set(PauseState::Paused),
+        PauseState::Paused => next_state.set(PauseState::Running),
+    }
+}
+
+fn pause_on_focus_loss(
+    mut focus_events: EventReader<WindowFocused>,
+    settings: Res<Settings>,
+    mut next_state: ResMut<NextState<PauseState>>,
+) {
+    for event in focus_events.read() {
+        if !event.focused && settings.pause_on_focus_loss {
+            next_state.set(PauseState::Paused);
+        }
+    }
+}
+
+fn setup_pause_menu(mut commands: Commands, scene_assets: Res<SceneAssets>) {
+    let font = scene_assets.font.clone();
+
+    let screen = commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(12.),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::rgba_u8(0, 0, 0, 180)),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Paused",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 64.0,
+                    color: TEXT_COLOR,
+                },
+            ).with_style(Style {
+                margin: UiRect::bottom(Val::Px(16.)),
+                ..default()
+            }));
+
+            spawn_button(parent, &font, "Resume", PauseButton::Resume);
+            spawn_button(parent, &font, "Restart", PauseButton::Restart);
+            spawn_button(parent, &font, "Settings", PauseButton::Settings);
+            spawn_button(parent, &font, "Quit to menu", PauseButton::QuitToMenu);
+        })
+        .id();
+    commands.insert_resource(PauseMenuData { screen });
+}
+
+fn pause_menu(
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+    mut next_app_state: ResMut<NextState<AppState>>,
+    mut next_settings_state: ResMut<NextState<SettingsState>>,
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &PauseButton),
+        Changed<Interaction>,
+    >,
+) {
+    for (interaction, mut color, button) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                match button {
+                    PauseButton::Resume => next_pause_state.set(PauseState::Running),
+                    PauseButton::Restart => next_app_state.set(AppState::Restarting),
+                    PauseButton::Settings => next_settings_state.set(SettingsState::Open),
+                    PauseButton::QuitToMenu => next_app_state.set(AppState::Menu),
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn cleanup_pause_menu(mut commands: Commands, pause_menu: Res<PauseMenuData>) {
+    commands.entity(pause_menu.screen).despawn_recursive();
+    commands.remove_resource::<PauseMenuData>();
+}
+
+fn resume(mut next_state: ResMut<NextState<PauseState>>) {
+    next_state.set(PauseState::Running);
+}
+
+fn restart_run(mut next_state: ResMut<NextState<AppState>>) {
+    next_state.set(AppState::InGame);
+}
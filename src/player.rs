@@ -1,30 +1,48 @@
 use bevy::{prelude::*, window::PrimaryWindow};
+use rand::Rng;
 
 pub const PLAYER_SIZE: f32 = 20.0;
 const PLAYER_SCALE: f32 = 0.5;
-pub const HALF_PLAYER_SIZE: f32 = 16.0;
 pub const PLAYER_TIME_UNTIL_NEXT_SHOT: f32 = 0.15;
+// For this long after the ship (re)spawns, no asteroid appears within SPAWN_PROTECTION_RADIUS of it
+pub const SPAWN_PROTECTION_TIME: f32 = 3.0;
+pub const SPAWN_PROTECTION_RADIUS: f32 = 350.0;
+// Hull points the ship has for every life
+pub const PLAYER_HULL: u32 = 3;
+// Enough to smash any asteroid the ship rams
+const PLAYER_RAM_DAMAGE: u32 = 10;
+// Score for an asteroid smashed by ramming it
+const RAM_SCORE: u32 = 50;
+// How long the ship is gone after losing a life
+const RESPAWN_DELAY: f32 = 1.5;
+// The ship comes back in the center if no asteroid is this close to it, otherwise somewhere else
+// that's clear
+const RESPAWN_CLEAR_RADIUS: f32 = 150.0;
+// After respawning, asteroids pass through the ship for this long
+pub const INVULNERABLE_TIME: f32 = 2.0;
+// How many times a second the ship blinks while invulnerable
+const INVULNERABLE_BLINK_RATE: f32 = 8.0;
 
-pub const MISSILE_SPEED: f32 = 500.0;
-pub const MISSILE_SIZE: f32 = 5.0;
-
-use crate::{schedules::InGameSet, movement::{MovingObjectBundle, Velocity, Acceleration}, collisions::Collider, asset_loader::SceneAssets, state::AppState};
+use crate::{schedules::InGameSet, movement::{MovingObjectBundle, Velocity, Acceleration, Wraps, play_field_size, wrapped_offset, clear_position, random_position}, collisions::{Collider, CollisionLayers}, asset_loader::SceneAssets, state::AppState, despawn::InGameScoped, weapon::{Weapon, FireCooldown, fire_weapon}, health::{Health, ContactDamage, Destroyed}, asteroids::Asteroid, schedules::DamageSet, shield::PlayerShield, hyperspace::{HyperspaceCooldown, InHyperspace}, input::{Action, TickActions}, rng::{GameRng, RngStream}, interpolation::PreviousTransform, daily::RunMode};
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) { 
-        app.init_resource::<PlayerFirerateTimer>();
         app.add_systems(OnEnter(AppState::InGame), spawn_player);
-        app.add_systems(Update, (
+        app.add_systems(FixedUpdate, (
             player_movement,
-            confine_player_movement,
             player_weapon,
-            player_shield,
         ).run_if(in_state(AppState::InGame)).chain().in_set(InGameSet::UserInput));
-        app.add_systems(Update, (
-            tick_player_shot_timer,
-        ).run_if(in_state(AppState::InGame)));
+        app.add_systems(FixedUpdate, (
+            tick_spawn_protection,
+            respawn_player,
+            blink_invulnerable,
+        ).run_if(in_state(AppState::InGame)).in_set(InGameSet::EntityUpdates));
+        app.add_systems(FixedUpdate, (
+            handle_player_destroyed,
+            score_destroyed_asteroids,
+        ).run_if(in_state(AppState::InGame)).in_set(DamageSet::React));
     } 
 }
 
@@ -39,28 +57,78 @@ pub struct Player {
 #[derive(Component, Debug)]
 pub struct PlayerBullet;
 
+// Keeps new asteroids from appearing right next to a ship that just (re)spawned
 #[derive(Component, Debug)]
-pub struct PlayerShield;
+pub struct SpawnProtection {
+    pub timer: Timer,
+}
+
+// The ship is gone for a moment after losing a life, and comes back once the timer runs out
+#[derive(Component, Debug)]
+pub struct Respawning {
+    pub timer: Timer,
+}
+
+impl Default for Respawning {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(RESPAWN_DELAY, TimerMode::Once),
+        }
+    }
+}
+
+// Asteroids pass through the ship while this is on it
+#[derive(Component, Debug)]
+pub struct Invulnerable {
+    pub timer: Timer,
+}
+
+impl Default for Invulnerable {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(INVULNERABLE_TIME, TimerMode::Once),
+        }
+    }
+}
+
+impl Default for SpawnProtection {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(SPAWN_PROTECTION_TIME, TimerMode::Once),
+        }
+    }
+}
 
 // Player Data
 #[derive(Component)]
 pub struct PlayerData {
     pub lives: u32,
-    rpm: f32,
-    pub can_fire: bool,
     pub boosting: Vec3,
     pub acceleration: f32,
     pub max_speed: f32,
     pub rotation_speed: f32,
+    // Multiplies how fast the equipped weapon fires
     pub firerate: f32,
     pub stats: Stats,
 }
 // Stats for the player
+#[derive(Debug, Clone)]
 pub struct Stats {
     pub score: u32,
     pub asteroids_destroyed: u32,
-    level: u32,
+    pub level: u32,
     pub shots_fired: u32,
+    pub shots_hit: u32,
+}
+
+impl Stats {
+    // Percentage of fired shots that hit something
+    pub fn accuracy(&self) -> f32 {
+        if self.shots_fired == 0 {
+            return 0.0;
+        }
+        self.shots_hit as f32 / self.shots_fired as f32 * 100.0
+    }
 }
 
 // Spawns the player bundle
@@ -68,178 +136,211 @@ fn spawn_player(
     mut commands: Commands, 
     window_query: Query<&Window, With<PrimaryWindow>>, 
     scene_assets: Res<SceneAssets>, 
+    run_mode: Res<RunMode>,
 ) {
     let window = window_query.get_single().unwrap();
     let player: Handle<Image> = scene_assets.spaceship.clone();
+    let weapon = run_mode.fixed_weapon().map_or_else(Weapon::default, Weapon::new);
 
     // Spawn the player
     commands.spawn((MovingObjectBundle {
         velocity: Velocity::new(Vec3::ZERO),
         acceleration: Acceleration::new(Vec3::ZERO),
-        collider: Collider::new(PLAYER_SIZE),
+        collider: player_collider(),
         sprite: SpriteBundle {
             transform: Transform::from_xyz(window.width() / 2.0, window.height() / 2.0, 0.0).with_scale(Vec3::new(PLAYER_SCALE, PLAYER_SCALE, 0.0)),
             texture: player, 
             ..default()
         }
-    }, Player {
+    }, Wraps, InGameScoped, SpawnProtection::default(), weapon, FireCooldown::default(), HyperspaceCooldown::default(),
+        Health::new(PLAYER_HULL), ContactDamage::new(PLAYER_RAM_DAMAGE), Player {
         player_data: PlayerData { 
-            lives: 3, 
-            rpm: 60.0,
-            can_fire: true,
+            lives: run_mode.lives(), 
             boosting: Vec3::ZERO,
             acceleration: 200.0,
             max_speed: 10.0,
             rotation_speed: 4.0,
-            firerate: PLAYER_TIME_UNTIL_NEXT_SHOT,
+            firerate: 1.0,
             stats: Stats { 
                 score: 0, 
                 asteroids_destroyed: 0, 
                 level: 1, 
-                shots_fired: 0 
+                shots_fired: 0,
+                shots_hit: 0,
             },
         },
     }));
 }
 
+pub fn player_collider() -> Collider {
+    Collider::new(
+        PLAYER_SIZE,
+        CollisionLayers::PLAYER,
+        CollisionLayers::ASTEROID | CollisionLayers::ENEMY | CollisionLayers::ENEMY_BULLET | CollisionLayers::PICKUP,
+    )
+}
+
 fn player_movement(
-    keyboard_input: Res<Input<KeyCode>>,
-    player_data: Query<&Player>,
-    mut player_query: Query<(&mut Transform, &mut Acceleration), With<Player>>, 
+    actions: Res<TickActions>,
+    player_data: Query<&Player, (Without<Respawning>, Without<InHyperspace>)>,
+    mut player_query: Query<(&mut Transform, &mut Acceleration), (With<Player>, Without<Respawning>, Without<InHyperspace>)>, 
     time: Res<Time>,
 ) {
     let Ok(player) = player_data.get_single() else {
-        println!("Brokey");
         return;
     };
     let Ok((mut transform, mut acceleration)) = player_query.get_single_mut() else {
         return;
     };
 
-    let mut rotation = 0.0;
-    let mut movement = 0.0;
-    let mut direction = Vec3::ZERO;
-
-    if keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up) {
-        movement = player.player_data.acceleration;
-        direction = transform.up();
-    }
-    // Rotate Left
-    if keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left) {
-        rotation = player.player_data.rotation_speed * time.delta_seconds();
-    }
-    // Slow Down
-    if keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down) {
-        movement = -player.player_data.acceleration;
-        direction = transform.up();
-    }
-    // Rotate Right
-    if keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right) {
-        rotation = -player.player_data.rotation_speed * time.delta_seconds();
+    // Sticks and triggers give partial values, so thrust and turning scale with them
+    let thrust = actions.value(Action::Thrust) - actions.value(Action::Reverse);
+    let max_turn = player.player_data.rotation_speed * time.delta_seconds();
+    match actions.aim() {
+        // Turn toward the cursor, no faster than the ship could turn with the keys
+        Some(target) => {
+            let to_target = target - transform.translation.xy();
+            if to_target != Vec2::ZERO {
+                let angle = transform.up().xy().angle_between(to_target);
+                transform.rotate_z(angle.clamp(-max_turn, max_turn));
+            }
+        }
+        None => {
+            let turn = actions.value(Action::RotateLeft) - actions.value(Action::RotateRight);
+            transform.rotate_z(turn * max_turn);
+        }
     }
-    transform.rotate_z(rotation);
 
-    if direction.length() > 0.0 {
-        direction = direction.normalize();
-    }
-    acceleration.value = movement * direction * time.delta_seconds(); 
+    let direction = transform.up();
+    acceleration.value = thrust * player.player_data.acceleration * direction;
 }
 
 fn player_weapon(
     mut commands: Commands, 
-    transform_query: Query<&Transform, With<Player>>, 
-    mut player_data: Query<&mut Player>,
-    timer: Res<PlayerFirerateTimer>, 
-    keyboard_input: Res<Input<KeyCode>>, 
-    mouse_input: Res<Input<MouseButton>>,
+    mut player_query: Query<(&Transform, &Weapon, &mut FireCooldown, &mut Player), (Without<Respawning>, Without<InHyperspace>)>,
+    actions: Res<TickActions>,
     scene_assets: Res<SceneAssets>
 ) {
-    let transform = transform_query.single();
-    let bullet: Handle<Image> = scene_assets.bullet.clone();
-    let Ok(mut player) = player_data.get_single_mut() else {
-        println!("Couldn't Get Player");
+    let Ok((transform, weapon, mut cooldown, mut player)) = player_query.get_single_mut() else {
         return; 
     };
 
-    if keyboard_input.pressed(KeyCode::Space) || mouse_input.pressed(MouseButton::Left) {
-        if player.player_data.can_fire {
-            commands.spawn((MovingObjectBundle {
-                velocity: Velocity::new(transform.up() * MISSILE_SPEED),
-                acceleration: Acceleration::new(Vec3::new(0.0, 0.0, 0.0)),
-                collider: Collider::new(MISSILE_SIZE),
-                sprite: SpriteBundle {
-                    transform: Transform {
-                        translation: Vec3::new(transform.translation.x, transform.translation.y, transform.translation.z) + (15.0 * transform.up()),
-                        rotation: transform.rotation,
-                        ..default()
-                    },
-                    texture: bullet,
-                    ..default()
-                }, 
-            }, PlayerBullet,));
-            player.player_data.stats.shots_fired += 1;
-            player.player_data.can_fire = false;
-        } else if timer.timer.finished() {
-            player.player_data.can_fire = true;
-        }
+    if actions.pressed(Action::Fire) && cooldown.ready() {
+        player.player_data.stats.shots_fired += fire_weapon(&mut commands, &scene_assets, weapon, transform);
+        cooldown.start(weapon.cooldown / player.player_data.firerate);
     } 
 
 }
 
-fn player_shield(mut commands: Commands, query: Query<Entity, With<Player>>, keyboard_input: Res<Input<KeyCode>>) {
-    let Ok(player) = query.get_single() else {
+fn tick_spawn_protection(mut commands: Commands, mut query: Query<(Entity, &mut SpawnProtection)>, time: Res<Time>) {
+    for (entity, mut protection) in query.iter_mut() {
+        if protection.timer.tick(time.delta()).just_finished() {
+            commands.entity(entity).remove::<SpawnProtection>();
+        }
+    }
+}
+
+// Running out of hull costs a life, and the last one ends the run
+fn handle_player_destroyed(
+    mut commands: Commands,
+    mut destroyed_events: EventReader<Destroyed>,
+    mut player_query: Query<(&mut Player, &mut Health, &mut Velocity, &mut Acceleration, &mut Visibility)>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for destroyed in destroyed_events.read() {
+        let Ok((mut player, mut health, mut velocity, mut acceleration, mut visibility)) = player_query.get_mut(destroyed.entity) else {
+            continue;
+        };
+
+        if player.player_data.lives > 1 {
+            player.player_data.lives -= 1;
+            health.current = health.max;
+            // Take the ship out of play until it respawns
+            velocity.value = Vec3::ZERO;
+            acceleration.value = Vec3::ZERO;
+            *visibility = Visibility::Hidden;
+            commands.entity(destroyed.entity)
+                .remove::<(Collider, PlayerShield, Invulnerable)>()
+                .insert(Respawning::default());
+        } else {
+            player.player_data.lives = 0;
+            next_state.set(AppState::GameOver);
+        }
+    }
+}
+
+fn score_destroyed_asteroids(
+    mut destroyed_events: EventReader<Destroyed>,
+    asteroid_query: Query<&Asteroid>,
+    mut player_query: Query<(Entity, &mut Player)>,
+) {
+    let Ok((ship, mut player)) = player_query.get_single_mut() else {
         return;
     };
 
-    if keyboard_input.pressed(KeyCode::Tab) {
-        commands.entity(player).insert(PlayerShield);
+    for destroyed in destroyed_events.read() {
+        let Ok(asteroid) = asteroid_query.get(destroyed.entity) else {
+            continue;
+        };
+        player.player_data.stats.asteroids_destroyed += 1;
+        player.player_data.stats.score += if destroyed.source == ship { RAM_SCORE } else { asteroid.score() };
     }
 }
 
-fn confine_player_movement(mut player_query: Query<(&mut Transform, &mut Velocity), With<Player>>, window_query: Query<&Window, With<PrimaryWindow>>) {
-    if let Ok((mut player_transform, mut player_velocity)) = player_query.get_single_mut() {
-        let window = window_query.get_single().unwrap();
-        
-        let x_min = 0.0 + HALF_PLAYER_SIZE;
-        let x_max = window.width() - HALF_PLAYER_SIZE;
-        let y_min = 0.0 + HALF_PLAYER_SIZE;
-        let y_max = window.height() - HALF_PLAYER_SIZE;
-
+// Brings the ship back once its respawn delay is over, somewhere clear of asteroids
+fn respawn_player(
+    mut commands: Commands,
+    mut player_query: Query<(Entity, &mut Respawning, &mut Transform, &mut Visibility)>,
+    asteroid_query: Query<&Transform, (With<Asteroid>, Without<Respawning>)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut rng: ResMut<GameRng>,
+    time: Res<Time>,
+) {
+    let window = window_query.get_single().unwrap();
+    let field = play_field_size(window);
+    let asteroids: Vec<Vec2> = asteroid_query.iter().map(|transform| transform.translation.xy()).collect();
 
-        let mut translation = player_transform.translation;
-        if translation.x < x_min {
-            translation.x = x_min;
-            player_velocity.value *= Vec3::new(0.0, 1.0, 0.0);
-        } else if translation.x > x_max {
-            translation.x = x_max;
-            player_velocity.value *= Vec3::new(0.0, 1.0, 0.0);
-        }
-        if translation.y < y_min {
-            translation.y = y_min;
-            player_velocity.value *= Vec3::new(1.0, 0.0, 0.0);
-        } else if translation.y > y_max {
-            translation.y = y_max;
-            player_velocity.value *= Vec3::new(1.0, 0.0, 0.0);
+    for (entity, mut respawning, mut transform, mut visibility) in player_query.iter_mut() {
+        if !respawning.timer.tick(time.delta()).just_finished() {
+            continue;
         }
 
-        player_transform.translation = translation;
+        transform.translation = safe_respawn_position(rng.stream(RngStream::Respawns), field, &asteroids).extend(transform.translation.z);
+        transform.rotation = Quat::IDENTITY;
+        *visibility = Visibility::Inherited;
+        commands.entity(entity)
+            .remove::<Respawning>()
+            .insert((player_collider(), Invulnerable::default(), SpawnProtection::default(), PreviousTransform(*transform)));
     }
 }
 
-#[derive(Resource)]
-pub struct PlayerFirerateTimer {
-    pub timer: Timer,
+// The center of the play field if it's clear, otherwise a random point as clear as can be found
+fn safe_respawn_position(rng: &mut impl Rng, field: Vec2, asteroids: &[Vec2]) -> Vec2 {
+    let center = field / 2.0;
+    let center_clear = asteroids.iter()
+        .all(|&asteroid| wrapped_offset(center, asteroid, field).length() >= RESPAWN_CLEAR_RADIUS);
+    if center_clear {
+        return center;
+    }
+
+    let avoid: Vec<(Vec2, f32)> = asteroids.iter().map(|&asteroid| (asteroid, RESPAWN_CLEAR_RADIUS)).collect();
+    clear_position(rng, field, &avoid, |rng| random_position(rng, field))
 }
 
-impl Default for PlayerFirerateTimer {
-    fn default() -> Self {
-        PlayerFirerateTimer {
-            timer: Timer::from_seconds(PLAYER_TIME_UNTIL_NEXT_SHOT, TimerMode::Repeating),
+fn blink_invulnerable(
+    mut commands: Commands,
+    // A ship in hyperspace stays hidden, and picks up blinking where it left off once it's back
+    mut query: Query<(Entity, &mut Invulnerable, &mut Visibility), Without<InHyperspace>>,
+    time: Res<Time>,
+) {
+    for (entity, mut invulnerable, mut visibility) in query.iter_mut() {
+        if invulnerable.timer.tick(time.delta()).just_finished() {
+            *visibility = Visibility::Inherited;
+            commands.entity(entity).remove::<Invulnerable>();
+            continue;
         }
-    }
-}
 
-// Timer until player can fire another bullet.
-fn tick_player_shot_timer(mut bullet_firerate_timer: ResMut<PlayerFirerateTimer>, time: Res<Time>) {
-    bullet_firerate_timer.timer.tick(time.delta());
+        let blink_on = ((invulnerable.timer.elapsed_secs() * INVULNERABLE_BLINK_RATE) as u32).is_multiple_of(2);
+        *visibility = if blink_on { Visibility::Inherited } else { Visibility::Hidden };
+    }
 }
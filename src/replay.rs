@@ -0,0 +1,378 @@
+use std::{fs, hash::Hasher, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    state::AppState,
+    schedules::TickInputSet,
+    input::{TickActions, TickInput},
+    rng::{GameRng, SeedSource},
+    storage::{data_dir, load_ron, save_ron_compact},
+    movement::{Velocity, play_field_size},
+    player::Player,
+    health::Health,
+    asteroids::Wave,
+    daily::RunMode,
+};
+
+// Folder in the data directory replays are saved to. Only the newest few are kept.
+const REPLAY_DIR: &str = "replays";
+const MAX_SAVED_REPLAYS: usize = 10;
+// Changes to the simulation can make older replays play out differently. Bump this when that
+// happens, so watching one warns that it may not match.
+const REPLAY_VERSION: u32 = 5;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::InGame), start_session)
+            .add_systems(FixedUpdate, replay_tick.run_if(in_state(AppState::InGame)).in_set(TickInputSet::Replay))
+            .add_systems(OnExit(AppState::InGame), finish_session);
+    }
+}
+
+// Everything needed to play a run out again: its seed and the input on every tick
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    // The rules the run was played by. Older replays were all standard runs.
+    #[serde(default)]
+    pub mode: RunMode,
+    // Length of a simulation tick, in microseconds
+    pub timestep: u64,
+    // The play field is the window, and where things spawn and wrap depends on its size
+    pub field: Vec2,
+    // The input on every tick in order, with runs of the same input folded into a count
+    pub inputs: Vec<(u32, TickInput)>,
+    pub ticks: u32,
+    pub score: u32,
+    // Fingerprint of the state the run ended in
+    pub checksum: u64,
+}
+
+impl Replay {
+    fn new(seed: u64, mode: RunMode, timestep: u64, field: Vec2) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            seed,
+            mode,
+            timestep,
+            field,
+            inputs: Vec::new(),
+            ticks: 0,
+            score: 0,
+            checksum: 0,
+        }
+    }
+
+    fn record(&mut self, input: TickInput) {
+        match self.inputs.last_mut() {
+            Some((count, last)) if *last == input => *count += 1,
+            _ => self.inputs.push((1, input)),
+        }
+        self.ticks += 1;
+    }
+}
+
+// A replay picked to be watched. The next run plays it back instead of recording a new one.
+#[derive(Resource, Debug)]
+struct PendingReplay(Replay);
+
+// Whether the last replay watched ended the same way the recorded run did
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayCheck {
+    Matched,
+    Diverged,
+}
+
+// The run being recorded or played back
+#[derive(Resource, Debug)]
+struct ReplaySession {
+    replay: Replay,
+    playing: bool,
+    // Ticks simulated so far
+    tick: u32,
+    // While playing back, the entry in `replay.inputs` that's up next and how many of its ticks
+    // have been used
+    entry: usize,
+    used: u32,
+}
+
+impl ReplaySession {
+    fn recording(replay: Replay) -> Self {
+        Self { replay, playing: false, tick: 0, entry: 0, used: 0 }
+    }
+
+    fn playing(replay: Replay) -> Self {
+        Self { replay, playing: true, tick: 0, entry: 0, used: 0 }
+    }
+
+    fn next_input(&mut self) -> Option<TickInput> {
+        let &(count, input) = self.replay.inputs.get(self.entry)?;
+        self.used += 1;
+        if self.used >= count {
+            self.entry += 1;
+            self.used = 0;
+        }
+        Some(input)
+    }
+}
+
+// Makes the next run play `replay` back. The window is resized to the play field it was recorded
+// on, and it's played by the rules it was recorded with, since the run can't play out the same
+// any other way.
+pub fn watch_replay(commands: &mut Commands, window: &mut Window, replay: Replay) {
+    if play_field_size(window) != replay.field {
+        window.resolution.set(replay.field.x, replay.field.y);
+    }
+    commands.insert_resource(replay.mode);
+    commands.insert_resource(PendingReplay(replay));
+}
+
+// The most recently saved replay, if there is one that can be read
+pub fn latest_replay() -> Option<Replay> {
+    let dir = replay_dir()?;
+    saved_replays(&dir).last().and_then(load_ron)
+}
+
+fn replay_dir() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(REPLAY_DIR))
+}
+
+// Saved replay files, oldest first
+fn saved_replays(dir: &PathBuf) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|extension| extension == "ron")
+                && path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("replay-"))
+        })
+        .collect();
+    // The names hold a fixed width timestamp, so they sort by age
+    paths.sort();
+    paths
+}
+
+fn save_replay(replay: &Replay) {
+    let Some(dir) = replay_dir() else {
+        return;
+    };
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_millis());
+    if save_ron_compact(&dir.join(format!("replay-{:016}.ron", timestamp)), replay).is_err() {
+        return;
+    }
+
+    let saved = saved_replays(&dir);
+    for old in &saved[..saved.len().saturating_sub(MAX_SAVED_REPLAYS)] {
+        if let Err(error) = fs::remove_file(old) {
+            warn!("Couldn't remove old replay {}: {}", old.display(), error);
+        }
+    }
+}
+
+fn start_session(
+    mut commands: Commands,
+    pending: Option<Res<PendingReplay>>,
+    mut rng: ResMut<GameRng>,
+    seed_source: Res<SeedSource>,
+    run_mode: Res<RunMode>,
+    fixed_time: Res<Time<Fixed>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
+    let window = window_query.get_single().unwrap();
+    let timestep = fixed_time.timestep().as_micros() as u64;
+    commands.remove_resource::<ReplayCheck>();
+
+    let session = if let Some(pending) = pending {
+        commands.remove_resource::<PendingReplay>();
+        let replay = pending.0.clone();
+        if replay.version != REPLAY_VERSION || replay.timestep != timestep {
+            warn!("This replay was recorded by a different version of the game and may not play out the same");
+        }
+        *rng = GameRng::new(replay.seed);
+        ReplaySession::playing(replay)
+    } else {
+        let seed = run_mode.seed().unwrap_or_else(|| seed_source.next_seed());
+        *rng = GameRng::new(seed);
+        ReplaySession::recording(Replay::new(seed, *run_mode, timestep, play_field_size(window)))
+    };
+    commands.insert_resource(session);
+}
+
+// Records the tick's input, or swaps it for the recorded one when playing back
+fn replay_tick(
+    mut session: ResMut<ReplaySession>,
+    mut tick_actions: ResMut<TickActions>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if session.playing {
+        let Some(input) = session.next_input() else {
+            // The recording is over, so the run is too. This also stops this tick from running.
+            next_state.set(AppState::GameOver);
+            return;
+        };
+        tick_actions.current = input;
+    } else {
+        let input = tick_actions.current;
+        session.replay.record(input);
+    }
+    session.tick += 1;
+}
+
+// Saves the run that just ended, or checks the one that was played back against its recording
+fn finish_session(
+    mut commands: Commands,
+    session: Option<Res<ReplaySession>>,
+    player_query: Query<(&Player, Option<&Health>)>,
+    body_query: Query<(&Transform, &Velocity, Option<&Health>)>,
+    wave: Res<Wave>,
+) {
+    let Some(session) = session else {
+        return;
+    };
+    commands.remove_resource::<ReplaySession>();
+    let checksum = state_checksum(session.tick, &wave, &player_query, &body_query);
+
+    if session.playing {
+        let matched = session.tick == session.replay.ticks && checksum == session.replay.checksum;
+        if matched {
+            info!("Replay matched the recording after {} ticks", session.tick);
+        } else {
+            warn!(
+                "Replay diverged: ended after {} of {} ticks with checksum {:016x} instead of {:016x}",
+                session.tick, session.replay.ticks, checksum, session.replay.checksum,
+            );
+        }
+        commands.insert_resource(if matched { ReplayCheck::Matched } else { ReplayCheck::Diverged });
+    } else if session.tick > 0 {
+        let mut replay = session.replay.clone();
+        replay.score = player_query.iter().map(|(player, _)| player.player_data.stats.score).sum();
+        replay.checksum = checksum;
+        save_replay(&replay);
+    }
+}
+
+// Fingerprints the state of a run: the player's stats and where everything is and how it's moving
+fn state_checksum(
+    tick: u32,
+    wave: &Wave,
+    player_query: &Query<(&Player, Option<&Health>)>,
+    body_query: &Query<(&Transform, &Velocity, Option<&Health>)>,
+) -> u64 {
+    let mut hasher = Fnv1a::default();
+    hasher.write_u32(tick);
+    hasher.write_u32(wave.number);
+
+    for (player, health) in player_query.iter() {
+        let data = &player.player_data;
+        for value in [data.lives, data.stats.score, data.stats.asteroids_destroyed, data.stats.shots_fired, data.stats.shots_hit] {
+            hasher.write_u32(value);
+        }
+        hasher.write_u32(health.map_or(0, |health| health.current));
+    }
+
+    // Query order isn't part of the run's state, so hash every body on its own and sort them
+    let mut bodies: Vec<u64> = body_query.iter()
+        .map(|(transform, velocity, health)| {
+            let mut body = Fnv1a::default();
+            let rotation = transform.rotation.to_array();
+            for value in transform.translation.to_array().iter().chain(&rotation).chain(&velocity.value.to_array()) {
+                body.write_u32(value.to_bits());
+            }
+            body.write_u32(health.map_or(0, |health| health.current));
+            body.finish()
+        })
+        .collect();
+    bodies.sort_unstable();
+    for body in bodies {
+        hasher.write_u64(body);
+    }
+    hasher.finish()
+}
+
+// FNV-1a. Unlike the standard library's hasher, it's guaranteed to hash the same way everywhere, so
+// a checksum in a replay file stays meaningful.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(thrust: u8, aim: Option<IVec2>) -> TickInput {
+        let mut input = TickInput { aim, ..default() };
+        input.actions[0] = thrust;
+        input
+    }
+
+    #[test]
+    fn recorded_inputs_play_back_tick_for_tick() {
+        let idle = TickInput::default();
+        let thrusting = input(255, None);
+        let aiming = input(128, Some(IVec2::new(40, -3)));
+        // Repeated, changing and trailing inputs, ending on a run of the same one
+        let ticks = [idle, idle, idle, thrusting, aiming, thrusting, thrusting, idle, aiming, aiming];
+
+        let mut replay = Replay::new(7, RunMode::Standard, 15_625, Vec2::new(800., 600.));
+        for tick in ticks {
+            replay.record(tick);
+        }
+        assert_eq!(replay.ticks, ticks.len() as u32);
+        assert_eq!(replay.inputs.len(), 6);
+
+        let mut session = ReplaySession::playing(replay);
+        let played: Vec<TickInput> = std::iter::from_fn(|| session.next_input()).collect();
+        assert_eq!(played, ticks);
+    }
+
+    #[test]
+    fn an_empty_recording_plays_nothing() {
+        let mut session = ReplaySession::playing(Replay::new(7, RunMode::Standard, 15_625, Vec2::ONE));
+        assert_eq!(session.next_input(), None);
+    }
+
+    // Saved checksums are only comparable if this stays standard FNV-1a
+    #[test]
+    fn fnv1a_matches_the_reference_values() {
+        let mut hasher = Fnv1a::default();
+        assert_eq!(hasher.finish(), 0xcbf2_9ce4_8422_2325);
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+
+        let mut hasher = Fnv1a::default();
+        hasher.write(b"foobar");
+        assert_eq!(hasher.finish(), 0x85944171f73967e8);
+    }
+}
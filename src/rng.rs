@@ -0,0 +1,137 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::Deserialize;
+
+use crate::storage::{config_dir, load_ron};
+
+// Command line flag that fixes the seed of every run, like `--seed 1234`
+const SEED_FLAG: &str = "--seed";
+// File in the config directory that can fix the seed instead, like `(seed: Some(1234))`
+const SEED_CONFIG_FILE: &str = "seed.ron";
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        let seed_source = SeedSource::from_args_and_config();
+        app.insert_resource(GameRng::new(seed_source.next_seed()))
+            .insert_resource(seed_source);
+    }
+}
+
+// Independent sequences of random numbers, one for each part of the game. Drawing more numbers
+// from one stream never changes what another one gives, so a new feature only shifts its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RngStream {
+    // Where new waves of asteroids appear
+    Spawns = 0,
+    // How destroyed asteroids break apart
+    Fragments = 1,
+    // Where a hyperspace jump lands and whether the ship survives it
+    Hyperspace = 2,
+    // Pickups and enemies don't exist yet, but they'll have streams of their own waiting
+    Loot = 3,
+    Ai = 4,
+    // Where the ship comes back after losing a life, so dying more often doesn't move later waves
+    Respawns = 5,
+}
+
+impl RngStream {
+    // Each stream is seeded from its number, so new streams go on the end with the next one
+    const ALL: [RngStream; 6] = [
+        RngStream::Spawns,
+        RngStream::Fragments,
+        RngStream::Hyperspace,
+        RngStream::Loot,
+        RngStream::Ai,
+        RngStream::Respawns,
+    ];
+}
+
+// Every random decision the simulation makes draws from here, so a run can be played out again
+// from its seed
+#[derive(Resource, Debug)]
+pub struct GameRng {
+    seed: u64,
+    streams: [StdRng; RngStream::ALL.len()],
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            streams: RngStream::ALL.map(|stream| StdRng::seed_from_u64(stream_seed(seed, stream))),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn stream(&mut self, stream: RngStream) -> &mut StdRng {
+        &mut self.streams[stream as usize]
+    }
+}
+
+// Spreads the run's seed out into one per stream
+fn stream_seed(seed: u64, stream: RngStream) -> u64 {
+    seed ^ (stream as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SeedConfig {
+    seed: Option<u64>,
+}
+
+// Where the seed for a new run comes from
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeedSource {
+    // Every run plays out from the same seed, given on the command line or in the config
+    Fixed(u64),
+    // Every run gets a new seed from the clock
+    TimeOfDay,
+}
+
+impl SeedSource {
+    // The command line wins over the config, and without either every run is different
+    fn from_args_and_config() -> Self {
+        if let Some(seed) = seed_from_args(std::env::args().skip(1)) {
+            return SeedSource::Fixed(seed);
+        }
+        let config: Option<SeedConfig> = config_dir().and_then(|dir| load_ron(&dir.join(SEED_CONFIG_FILE)));
+        match config.and_then(|config| config.seed) {
+            Some(seed) => SeedSource::Fixed(seed),
+            None => SeedSource::TimeOfDay,
+        }
+    }
+
+    pub fn next_seed(&self) -> u64 {
+        match *self {
+            SeedSource::Fixed(seed) => seed,
+            SeedSource::TimeOfDay => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_nanos() as u64),
+        }
+    }
+}
+
+// Reads `--seed 1234` or `--seed=1234`. A seed that isn't a number is ignored with a warning.
+fn seed_from_args(mut args: impl Iterator<Item = String>) -> Option<u64> {
+    while let Some(arg) = args.next() {
+        let value = if arg == SEED_FLAG {
+            args.next()
+        } else if let Some(value) = arg.strip_prefix(SEED_FLAG).and_then(|rest| rest.strip_prefix('=')) {
+            Some(value.to_string())
+        } else {
+            continue;
+        };
+
+        match value.as_deref().map(str::parse::<u64>) {
+            Some(Ok(seed)) => return Some(seed),
+            _ => warn!("Ignoring {} without a number after it", SEED_FLAG),
+        }
+    }
+    None
+}
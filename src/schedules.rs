@@ -1,7 +1,8 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, ecs::schedule::ExecutorKind};
 
-use crate::state::AppState;
+use crate::state::{AppState, PauseState};
 
+// The simulation of a run. Every set runs in `FixedUpdate`, once per tick.
 #[derive(Debug, Hash, PartialEq, Clone, SystemSet, Eq)]
 pub enum InGameSet {
     UserInput,
@@ -10,22 +11,58 @@ pub enum InGameSet {
     DespawnEntities,
 }
 
+// Steps of the damage pipeline, which runs inside `InGameSet::DespawnEntities`. Collision handlers
+// deal damage, it is applied to health, and then owners react to whatever was destroyed.
+#[derive(Debug, Hash, PartialEq, Clone, SystemSet, Eq)]
+pub enum DamageSet {
+    Deal,
+    Apply,
+    React,
+}
+
+// Gathers the input for a tick before the rest of the simulation runs. The devices are sampled
+// first, then a replay gets to record that input or swap it for the recorded one.
+#[derive(Debug, Hash, PartialEq, Clone, SystemSet, Eq)]
+pub enum TickInputSet {
+    Sample,
+    Replay,
+}
+
 pub struct SchedulePlugin;
 
 impl Plugin for SchedulePlugin {
     fn build(&self, app: &mut App) {
-        app.configure_sets(Update, (
+        // Systems always run in the same order on one thread, so a run plays out the same way
+        // every time it is given the same seed and input
+        app.edit_schedule(FixedUpdate, |schedule| {
+            schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+        });
+
+        app.configure_sets(FixedUpdate, (
+            TickInputSet::Sample,
+            TickInputSet::Replay,
+        ).chain().before(InGameSet::DespawnEntities).run_if(simulation_running),
+        ).configure_sets(FixedUpdate, (
             InGameSet::DespawnEntities,
             // Flush Commands
             InGameSet::UserInput,
             InGameSet::EntityUpdates,
             InGameSet::CollisionDetection,
-        ).chain(),
-        ).add_systems(Update, apply_deferred.run_if(in_state(AppState::InGame))
+        ).chain().run_if(simulation_running),
+        ).configure_sets(FixedUpdate, (
+            DamageSet::Deal,
+            DamageSet::Apply,
+            DamageSet::React,
+        ).chain().in_set(InGameSet::DespawnEntities),
+        ).add_systems(FixedUpdate, apply_deferred.run_if(in_state(AppState::InGame))
             .after(InGameSet::DespawnEntities)
             .before(InGameSet::UserInput),
         );
-        
     }
 }
 
+// Ticks stop while paused, and as soon as the run is on its way out, so a run that ends on some
+// tick always ends on that tick no matter how many more would have fit in the frame
+fn simulation_running(pause_state: Res<State<PauseState>>, next_state: Res<NextState<AppState>>) -> bool {
+    *pause_state.get() == PauseState::Running && next_state.0.is_none()
+}
@@ -0,0 +1,143 @@
+use std::{fs, path::PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{storage::{data_dir, load_ron, save_ron}, player::Stats};
+
+// How many scores a table keeps
+pub const MAX_SCORES: usize = 10;
+// Letters of initials that go with a score
+pub const INITIALS_LEN: usize = 3;
+// File in the data directory with the best scores of standard runs
+const HIGH_SCORES_FILE: &str = "high_scores.ron";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    // Tables saved before initials were asked for don't have them
+    #[serde(default = "unknown_initials")]
+    pub initials: String,
+    pub score: u32,
+    pub level: u32,
+}
+
+fn unknown_initials() -> String {
+    "-".repeat(INITIALS_LEN)
+}
+
+impl ScoreEntry {
+    pub fn new(initials: String, stats: &Stats) -> Self {
+        Self {
+            initials,
+            score: stats.score,
+            level: stats.level,
+        }
+    }
+}
+
+// The best scores saved in one file, highest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScoreTable {
+    pub entries: Vec<ScoreEntry>,
+}
+
+impl ScoreTable {
+    // A missing or unreadable file is an empty table. An unreadable one is moved aside first, so
+    // the next save doesn't overwrite scores that could still be fixed by hand.
+    pub fn load(path: &PathBuf) -> Self {
+        let Some(mut table) = load_ron::<ScoreTable>(path) else {
+            if path.exists() {
+                let backup = path.with_extension("ron.bak");
+                match fs::rename(path, &backup) {
+                    Ok(()) => warn!("Moved the unreadable {} to {}", path.display(), backup.display()),
+                    Err(error) => warn!("Couldn't move the unreadable {} aside: {}", path.display(), error),
+                }
+            }
+            return Self::default();
+        };
+        // Someone may have edited the file by hand
+        table.entries.sort_by_key(|entry| std::cmp::Reverse(entry.score));
+        table.entries.truncate(MAX_SCORES);
+        table
+    }
+
+    pub fn save(&self, path: &PathBuf) {
+        // Failures are already logged, and a lost score isn't worth stopping for
+        let _ = save_ron(path, self);
+    }
+
+    // Whether `score` would make it onto the table. A run that scored nothing never does.
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0 && (self.entries.len() < MAX_SCORES || self.entries.iter().any(|entry| entry.score < score))
+    }
+
+    // Adds `entry` and returns its place on the table, or None if it didn't make it. An entry goes
+    // below earlier ones with the same score.
+    pub fn insert(&mut self, entry: ScoreEntry) -> Option<usize> {
+        if !self.qualifies(entry.score) {
+            return None;
+        }
+        let rank = self.entries.iter().position(|other| other.score < entry.score).unwrap_or(self.entries.len());
+        self.entries.insert(rank, entry);
+        self.entries.truncate(MAX_SCORES);
+        Some(rank)
+    }
+
+    pub fn best(&self) -> Option<u32> {
+        self.entries.first().map(|entry| entry.score)
+    }
+}
+
+pub fn high_scores_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(HIGH_SCORES_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(initials: &str, score: u32) -> ScoreEntry {
+        ScoreEntry { initials: initials.to_string(), score, level: 1 }
+    }
+
+    // Ten scores of 100, 200, ... 1000
+    fn full_table() -> ScoreTable {
+        let mut table = ScoreTable::default();
+        for score in (1..=MAX_SCORES as u32).rev() {
+            table.entries.push(entry("AAA", score * 100));
+        }
+        table
+    }
+
+    #[test]
+    fn tying_the_last_place_does_not_qualify() {
+        let mut table = full_table();
+        assert!(!table.qualifies(100));
+        assert_eq!(table.insert(entry("NEW", 100)), None);
+        assert!(table.qualifies(101));
+    }
+
+    #[test]
+    fn new_entries_rank_below_equal_scores() {
+        let mut table = full_table();
+        assert_eq!(table.insert(entry("NEW", 500)), Some(6));
+        assert_eq!(table.entries[5], entry("AAA", 500));
+        assert_eq!(table.entries[6], entry("NEW", 500));
+    }
+
+    #[test]
+    fn the_table_is_cut_to_its_size() {
+        let mut table = full_table();
+        assert_eq!(table.insert(entry("NEW", 2000)), Some(0));
+        assert_eq!(table.entries.len(), MAX_SCORES);
+        assert_eq!(table.entries.last().map(|entry| entry.score), Some(200));
+    }
+
+    #[test]
+    fn scoring_nothing_never_qualifies() {
+        let mut table = ScoreTable::default();
+        assert!(!table.qualifies(0));
+        assert_eq!(table.insert(entry("NEW", 0)), None);
+        assert!(table.entries.is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+use bevy::{prelude::*, ui::FocusPolicy};
+
+use crate::{asset_loader::SceneAssets, input::{Action, ActionState, ControlScheme}, controls::ControlsState, menu::{NORMAL_BUTTON, HOVERED_BUTTON, PRESSED_BUTTON, TEXT_COLOR, spawn_button}};
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Settings>()
+            .add_state::<SettingsState>()
+            .add_systems(OnEnter(SettingsState::Open), setup_settings)
+            .add_systems(Update, (
+                settings_menu,
+                // Escape closes the controls screen first when it is open
+                close_settings.run_if(in_state(ControlsState::Closed)),
+            ).run_if(in_state(SettingsState::Open)))
+            .add_systems(OnExit(SettingsState::Open), cleanup_settings);
+    }
+}
+
+// Player preferences that can be changed from the settings screen
+#[derive(Resource, Debug)]
+pub struct Settings {
+    pub pause_on_focus_loss: bool,
+    pub control_scheme: ControlScheme,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            pause_on_focus_loss: true,
+            control_scheme: ControlScheme::default(),
+        }
+    }
+}
+
+// Whether the settings screen is showing. It draws over whatever screen opened it.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
+pub enum SettingsState {
+    #[default]
+    Closed,
+    Open,
+}
+
+#[derive(Resource)]
+struct SettingsData {
+    screen: Entity,
+}
+
+#[derive(Component, Clone, Copy)]
+enum SettingsButton {
+    PauseOnFocusLoss,
+    ControlScheme,
+    Controls,
+    Back,
+}
+
+impl SettingsButton {
+    fn label(self, settings: &Settings) -> String {
+        match self {
+            SettingsButton::PauseOnFocusLoss => format!("Pause when unfocused: {}", on_off(settings.pause_on_focus_loss)),
+            SettingsButton::ControlScheme => format!("Steering: {}", settings.control_scheme.label()),
+            SettingsButton::Controls => "Controls".to_string(),
+            SettingsButton::Back => "Back".to_string(),
+        }
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value { "On" } else { "Off" }
+}
+
+fn setup_settings(mut commands: Commands, settings: Res<Settings>, scene_assets: Res<SceneAssets>) {
+    let font = scene_assets.font.clone();
+
+    let screen = commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(12.),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::rgb_u8(10, 10, 10)),
+            // Keep the screen underneath from reacting to clicks
+            focus_policy: FocusPolicy::Block,
+            z_index: ZIndex::Global(10),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Settings",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 48.0,
+                    color: TEXT_COLOR,
+                },
+            ).with_style(Style {
+                margin: UiRect::bottom(Val::Px(16.)),
+                ..default()
+            }));
+
+            for button in [SettingsButton::PauseOnFocusLoss, SettingsButton::ControlScheme, SettingsButton::Controls, SettingsButton::Back] {
+                spawn_button(parent, &font, &button.label(&settings), button);
+            }
+        })
+        .id();
+    commands.insert_resource(SettingsData { screen });
+}
+
+fn settings_menu(
+    mut next_state: ResMut<NextState<SettingsState>>,
+    mut next_controls_state: ResMut<NextState<ControlsState>>,
+    mut settings: ResMut<Settings>,
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &SettingsButton, &Children),
+        Changed<Interaction>,
+    >,
+    mut text_query: Query<&mut Text>,
+) {
+    for (interaction, mut color, button, children) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                match button {
+                    SettingsButton::PauseOnFocusLoss => settings.pause_on_focus_loss = !settings.pause_on_focus_loss,
+                    SettingsButton::ControlScheme => settings.control_scheme = settings.control_scheme.next(),
+                    SettingsButton::Controls => next_controls_state.set(ControlsState::Open),
+                    SettingsButton::Back => next_state.set(SettingsState::Closed),
+                }
+                // Show the new value on the button
+                for &child in children.iter() {
+                    if let Ok(mut text) = text_query.get_mut(child) {
+                        text.sections[0].value = button.label(&settings);
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn close_settings(mut next_state: ResMut<NextState<SettingsState>>, actions: Res<ActionState>) {
+    if actions.just_pressed(Action::Pause) {
+        next_state.set(SettingsState::Closed);
+    }
+}
+
+fn cleanup_settings(mut commands: Commands, settings: Res<SettingsData>) {
+    commands.entity(settings.screen).despawn_recursive();
+    commands.remove_resource::<SettingsData>();
+}
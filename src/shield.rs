@@ -0,0 +1,159 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::{
+    schedules::InGameSet,
+    state::AppState,
+    player::{Player, Respawning},
+    hyperspace::InHyperspace,
+    input::{Action, TickActions},
+    asteroids::Asteroid,
+    asset_loader::SceneAssets,
+    movement::{Velocity, play_field_size, wrapped_offset},
+    collisions::{CollisionStarted, match_pair},
+};
+
+pub const SHIELD_MAX_ENERGY: f32 = 100.0;
+// Energy per second while the shield is up
+const SHIELD_DRAIN_RATE: f32 = 35.0;
+// Energy per second while the shield is down
+const SHIELD_RECHARGE_RATE: f32 = 15.0;
+// The shield can't be raised again until it has recharged this far
+const SHIELD_MIN_ENERGY: f32 = 10.0;
+// Energy taken by every asteroid the shield deflects
+const SHIELD_HIT_COST: f32 = 20.0;
+// Deflected asteroids leave at least this fast
+const SHIELD_KNOCKBACK_SPEED: f32 = 300.0;
+// Relative to the ship, so the bubble just wraps around it
+const SHIELD_BUBBLE_SCALE: f32 = 0.75;
+
+pub struct ShieldPlugin;
+
+impl Plugin for ShieldPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, (
+            attach_shield,
+            player_shield,
+        ).run_if(in_state(AppState::InGame)).chain().in_set(InGameSet::UserInput));
+        app.add_systems(FixedUpdate, (
+            update_shield_energy,
+            update_shield_bubble,
+        ).run_if(in_state(AppState::InGame)).chain().in_set(InGameSet::EntityUpdates));
+        app.add_systems(FixedUpdate, (
+            shield_knockback,
+        ).run_if(in_state(AppState::InGame)).in_set(InGameSet::DespawnEntities));
+    }
+}
+
+// Present while the shield is up
+#[derive(Component, Debug)]
+pub struct PlayerShield;
+
+#[derive(Component, Debug)]
+pub struct ShieldEnergy {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Default for ShieldEnergy {
+    fn default() -> Self {
+        Self {
+            current: SHIELD_MAX_ENERGY,
+            max: SHIELD_MAX_ENERGY,
+        }
+    }
+}
+
+// The bubble drawn around the ship while the shield is up
+#[derive(Component, Debug)]
+struct ShieldBubble;
+
+// Gives every new ship a full shield and a (hidden) bubble
+fn attach_shield(mut commands: Commands, query: Query<Entity, Added<Player>>, scene_assets: Res<SceneAssets>) {
+    for player in query.iter() {
+        commands.entity(player).insert(ShieldEnergy::default()).with_children(|parent| {
+            parent.spawn((SpriteBundle {
+                texture: scene_assets.shield.clone(),
+                transform: Transform::from_scale(Vec3::splat(SHIELD_BUBBLE_SCALE)),
+                visibility: Visibility::Hidden,
+                ..default()
+            }, ShieldBubble));
+        });
+    }
+}
+
+fn player_shield(
+    mut commands: Commands,
+    query: Query<(Entity, &ShieldEnergy, Has<PlayerShield>), (With<Player>, Without<Respawning>, Without<InHyperspace>)>,
+    actions: Res<TickActions>,
+) {
+    let Ok((player, energy, shielded)) = query.get_single() else {
+        return;
+    };
+
+    // Once up, the shield stays up until it's released or runs dry
+    let can_shield = if shielded { energy.current > 0.0 } else { energy.current >= SHIELD_MIN_ENERGY };
+
+    if actions.pressed(Action::Shield) && can_shield {
+        if !shielded {
+            commands.entity(player).insert(PlayerShield);
+        }
+    } else if shielded {
+        commands.entity(player).remove::<PlayerShield>();
+    }
+}
+
+fn update_shield_energy(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut ShieldEnergy, Has<PlayerShield>)>,
+    time: Res<Time>,
+) {
+    for (entity, mut energy, shielded) in query.iter_mut() {
+        if shielded {
+            energy.current = (energy.current - SHIELD_DRAIN_RATE * time.delta_seconds()).max(0.0);
+            if energy.current == 0.0 {
+                commands.entity(entity).remove::<PlayerShield>();
+            }
+        } else {
+            energy.current = (energy.current + SHIELD_RECHARGE_RATE * time.delta_seconds()).min(energy.max);
+        }
+    }
+}
+
+fn update_shield_bubble(
+    mut bubble_query: Query<(&Parent, &mut Visibility), With<ShieldBubble>>,
+    shield_query: Query<Has<PlayerShield>>,
+) {
+    for (parent, mut visibility) in bubble_query.iter_mut() {
+        let shielded = shield_query.get(parent.get()).unwrap_or(false);
+        *visibility = if shielded { Visibility::Inherited } else { Visibility::Hidden };
+    }
+}
+
+// Asteroids that hit a raised shield bounce off it, costing energy instead of a life
+fn shield_knockback(
+    mut collisions: EventReader<CollisionStarted>,
+    mut player_query: Query<(&Transform, &mut ShieldEnergy), (With<Player>, With<PlayerShield>)>,
+    mut asteroid_query: Query<(&Transform, &mut Velocity), (With<Asteroid>, Without<Player>)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
+    let window = window_query.get_single().unwrap();
+    let field = play_field_size(window);
+
+    for collision in collisions.read() {
+        let Some((player, asteroid)) = match_pair(collision.a, collision.b, &player_query, &asteroid_query) else {
+            continue;
+        };
+        let Ok((player_transform, mut energy)) = player_query.get_mut(player) else {
+            continue;
+        };
+        let Ok((asteroid_transform, mut velocity)) = asteroid_query.get_mut(asteroid) else {
+            continue;
+        };
+
+        let away = wrapped_offset(player_transform.translation.xy(), asteroid_transform.translation.xy(), field)
+            .normalize_or_zero()
+            .extend(0.0);
+        velocity.value = away * velocity.value.length().max(SHIELD_KNOCKBACK_SPEED);
+        energy.current = (energy.current - SHIELD_HIT_COST).max(0.0);
+    }
+}
@@ -0,0 +1,70 @@
+use bevy::{math::{IVec2, Vec2}, utils::HashMap};
+
+// Roughly the diameter of the largest asteroid, so most colliders only touch a few cells
+pub const DEFAULT_CELL_SIZE: f32 = 128.0;
+
+// Uniform grid broadphase. Circles are bucketed into every cell their bounding box touches, and
+// only circles that share a cell are handed back as candidate pairs.
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<usize>>,
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    // Empties every cell but keeps their allocations around for the next frame
+    pub fn clear(&mut self) {
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+    }
+
+    pub fn insert(&mut self, index: usize, center: Vec2, radius: f32) {
+        let min = self.cell(center - Vec2::splat(radius));
+        let max = self.cell(center + Vec2::splat(radius));
+
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                self.cells.entry(IVec2::new(x, y)).or_default().push(index);
+            }
+        }
+    }
+
+    // Every pair of indices that share at least one cell, as (lower, higher), sorted and without
+    // duplicates
+    pub fn candidate_pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+
+        for cell in self.cells.values() {
+            for (i, &a) in cell.iter().enumerate() {
+                for &b in &cell[i + 1..] {
+                    // The same circle can land in a cell twice when one of its wrapped copies
+                    // overlaps the original
+                    if a != b {
+                        pairs.push((a.min(b), a.max(b)));
+                    }
+                }
+            }
+        }
+
+        pairs.sort_unstable();
+        pairs.dedup();
+        pairs
+    }
+
+    fn cell(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+}
+
+impl Default for SpatialHash {
+    fn default() -> Self {
+        Self::new(DEFAULT_CELL_SIZE)
+    }
+}
@@ -5,4 +5,16 @@ pub enum AppState {
     #[default]
     Menu,
     InGame,
+    GameOver,
+    HighScores,
+    // Passes straight back to InGame, so leaving and re-entering InGame resets the run
+    Restarting,
+}
+
+// Whether the simulation of a run is ticking. Only meaningful while in AppState::InGame.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
 }
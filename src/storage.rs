@@ -0,0 +1,71 @@
+use std::{fs, path::PathBuf};
+
+use bevy::prelude::*;
+use serde::{Serialize, de::DeserializeOwned};
+
+// Folder the game's files go in, inside the platform's config or data directory
+const GAME_DIR: &str = "bevy_asteroids";
+
+// Where settings-like files live: ~/.config on Linux, Application Support on macOS and the roaming
+// AppData folder on Windows
+pub fn config_dir() -> Option<PathBuf> {
+    platform_dir("XDG_CONFIG_HOME", ".config")
+}
+
+// Where saved game data lives: ~/.local/share on Linux, otherwise the same place as the config
+pub fn data_dir() -> Option<PathBuf> {
+    platform_dir("XDG_DATA_HOME", ".local/share")
+}
+
+fn platform_dir(xdg_var: &str, home_fallback: &str) -> Option<PathBuf> {
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os(xdg_var)
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(home_fallback)))
+    };
+    base.map(|base| base.join(GAME_DIR))
+}
+
+// Reads a RON file. A missing file is normal and returns None quietly, an unreadable one is logged
+// and also returns None so the caller can fall back to its defaults.
+pub fn load_ron<T: DeserializeOwned>(path: &PathBuf) -> Option<T> {
+    let contents = fs::read_to_string(path).ok()?;
+    match ron::from_str(&contents) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            warn!("Ignoring {}: {}", path.display(), error);
+            None
+        }
+    }
+}
+
+// Writes a RON file, creating its folder if needed. Failures are logged and returned, but never
+// stop the game.
+pub fn save_ron<T: Serialize>(path: &PathBuf, value: &T) -> Result<(), String> {
+    write_file(path, ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()))
+}
+
+// Like `save_ron`, but all on one line, for files that are big and not meant to be edited by hand
+pub fn save_ron_compact<T: Serialize>(path: &PathBuf, value: &T) -> Result<(), String> {
+    write_file(path, ron::to_string(value))
+}
+
+fn write_file(path: &PathBuf, contents: Result<String, ron::Error>) -> Result<(), String> {
+    let result = (|| {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+        }
+        let contents = contents.map_err(|error| error.to_string())?;
+        fs::write(path, contents).map_err(|error| error.to_string())
+    })();
+
+    if let Err(error) = &result {
+        warn!("Couldn't save {}: {}", path.display(), error);
+    }
+    result
+}
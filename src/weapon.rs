@@ -0,0 +1,271 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::{
+    schedules::InGameSet,
+    state::AppState,
+    asteroids::Asteroid,
+    asset_loader::SceneAssets,
+    player::{Player, PlayerBullet, PLAYER_TIME_UNTIL_NEXT_SHOT},
+    movement::{MovingObjectBundle, Velocity, Acceleration, Wraps, play_field_size, wrapped_offset},
+    collisions::{Collider, CollisionLayers},
+    despawn::{Lifetime, InGameScoped},
+    bullets::Bullet,
+    input::{Action, TickActions},
+    daily::RunMode,
+};
+
+pub const MISSILE_SPEED: f32 = 500.0;
+pub const MISSILE_SIZE: f32 = 5.0;
+pub const MISSILE_LIFETIME: f32 = 1.5;
+// How far in front of the ship projectiles appear
+const MUZZLE_OFFSET: f32 = 15.0;
+
+pub struct WeaponPlugin;
+
+impl Plugin for WeaponPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, (
+            cycle_weapon,
+        ).run_if(in_state(AppState::InGame)).in_set(InGameSet::UserInput));
+        app.add_systems(FixedUpdate, (
+            tick_fire_cooldowns,
+            steer_homing_projectiles,
+        ).run_if(in_state(AppState::InGame)).in_set(InGameSet::EntityUpdates));
+    }
+}
+
+// The weapon presets, in the order the cycle key goes through them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeaponKind {
+    Single,
+    TripleSpread,
+    RapidFire,
+    PiercingRail,
+    HomingMissile,
+}
+
+impl WeaponKind {
+    pub fn next(self) -> Self {
+        match self {
+            WeaponKind::Single => WeaponKind::TripleSpread,
+            WeaponKind::TripleSpread => WeaponKind::RapidFire,
+            WeaponKind::RapidFire => WeaponKind::PiercingRail,
+            WeaponKind::PiercingRail => WeaponKind::HomingMissile,
+            WeaponKind::HomingMissile => WeaponKind::Single,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WeaponKind::Single => "Single shot",
+            WeaponKind::TripleSpread => "Triple spread",
+            WeaponKind::RapidFire => "Rapid fire",
+            WeaponKind::PiercingRail => "Piercing rail",
+            WeaponKind::HomingMissile => "Homing missile",
+        }
+    }
+}
+
+// Describes what a shooter fires and how often
+#[derive(Component, Debug, Clone)]
+pub struct Weapon {
+    pub kind: WeaponKind,
+    // Projectiles per shot
+    pub projectiles: u32,
+    // Angle in degrees between the outermost projectiles of a shot
+    pub spread: f32,
+    // Seconds between shots
+    pub cooldown: f32,
+    pub speed: f32,
+    pub lifetime: f32,
+    // How far a projectile can travel, if that runs out before its lifetime does
+    pub range: Option<f32>,
+    pub damage: u32,
+    // How many targets a projectile passes through before it is used up
+    pub pierce: u32,
+    // How many times a projectile bounces off the edges of the play field instead of wrapping
+    pub bounces: u32,
+    // Radians per second a projectile turns towards its target, if it homes at all
+    pub homing: Option<f32>,
+    pub color: Color,
+    // Sprite scale of the projectile
+    pub size: Vec2,
+}
+
+impl Weapon {
+    pub fn new(kind: WeaponKind) -> Self {
+        let single = Self {
+            kind,
+            projectiles: 1,
+            spread: 0.0,
+            cooldown: PLAYER_TIME_UNTIL_NEXT_SHOT,
+            speed: MISSILE_SPEED,
+            lifetime: MISSILE_LIFETIME,
+            range: None,
+            damage: 1,
+            pierce: 0,
+            bounces: 0,
+            homing: None,
+            color: Color::WHITE,
+            size: Vec2::ONE,
+        };
+
+        match kind {
+            WeaponKind::Single => single,
+            WeaponKind::TripleSpread => Self {
+                projectiles: 3,
+                spread: 30.0,
+                cooldown: 0.3,
+                lifetime: 1.0,
+                bounces: 1,
+                color: Color::rgb(1.0, 0.85, 0.4),
+                ..single
+            },
+            WeaponKind::RapidFire => Self {
+                cooldown: 0.06,
+                speed: 650.0,
+                lifetime: 0.8,
+                range: Some(400.0),
+                color: Color::rgb(0.5, 1.0, 0.5),
+                size: Vec2::splat(0.7),
+                ..single
+            },
+            WeaponKind::PiercingRail => Self {
+                cooldown: 0.6,
+                speed: 1200.0,
+                lifetime: 0.8,
+                damage: 3,
+                pierce: 3,
+                color: Color::rgb(0.5, 0.8, 1.0),
+                size: Vec2::new(0.6, 3.0),
+                ..single
+            },
+            WeaponKind::HomingMissile => Self {
+                cooldown: 0.5,
+                speed: 350.0,
+                lifetime: 2.5,
+                damage: 2,
+                homing: Some(4.0),
+                color: Color::rgb(1.0, 0.45, 0.35),
+                size: Vec2::new(1.0, 1.6),
+                ..single
+            },
+        }
+    }
+}
+
+impl Default for Weapon {
+    fn default() -> Self {
+        Self::new(WeaponKind::Single)
+    }
+}
+
+// Time until a shooter can fire again. It only runs after a shot, so the first shot after a
+// break always goes out straight away.
+#[derive(Component, Debug, Default)]
+pub struct FireCooldown {
+    pub remaining: f32,
+}
+
+impl FireCooldown {
+    pub fn ready(&self) -> bool {
+        self.remaining <= 0.0
+    }
+
+    pub fn start(&mut self, seconds: f32) {
+        self.remaining = seconds;
+    }
+}
+
+// Turns a projectile towards the nearest asteroid
+#[derive(Component, Debug)]
+pub struct Homing {
+    pub turn_rate: f32,
+}
+
+// Spawns one shot of `weapon` from the muzzle of `transform` and returns how many projectiles it fired
+pub fn fire_weapon(commands: &mut Commands, scene_assets: &SceneAssets, weapon: &Weapon, transform: &Transform) -> u32 {
+    for i in 0..weapon.projectiles {
+        // Spread the projectiles evenly, centred on the way the ship faces
+        let angle = if weapon.projectiles > 1 {
+            weapon.spread * (i as f32 / (weapon.projectiles - 1) as f32 - 0.5)
+        } else {
+            0.0
+        };
+        let rotation = transform.rotation * Quat::from_rotation_z(angle.to_radians());
+        let direction = rotation * Vec3::Y;
+
+        let mut projectile = commands.spawn((MovingObjectBundle {
+            velocity: Velocity::new(direction * weapon.speed),
+            acceleration: Acceleration::new(Vec3::ZERO),
+            collider: Collider::new(MISSILE_SIZE, CollisionLayers::PLAYER_BULLET, CollisionLayers::ASTEROID | CollisionLayers::ENEMY),
+            sprite: SpriteBundle {
+                transform: Transform {
+                    translation: transform.translation + MUZZLE_OFFSET * transform.up(),
+                    rotation,
+                    scale: weapon.size.extend(1.0),
+                },
+                texture: scene_assets.bullet.clone(),
+                sprite: Sprite {
+                    color: weapon.color,
+                    ..default()
+                },
+                ..default()
+            },
+        }, Bullet::new(weapon.damage, weapon.pierce, weapon.bounces, weapon.range), PlayerBullet, InGameScoped, Lifetime::new(weapon.lifetime)));
+
+        if weapon.bounces == 0 {
+            projectile.insert(Wraps);
+        }
+        if let Some(turn_rate) = weapon.homing {
+            projectile.insert(Homing { turn_rate });
+        }
+    }
+    weapon.projectiles
+}
+
+fn cycle_weapon(mut query: Query<&mut Weapon, With<Player>>, actions: Res<TickActions>, run_mode: Res<RunMode>) {
+    if !actions.just_pressed(Action::CycleWeapon) || run_mode.fixed_weapon().is_some() {
+        return;
+    }
+    for mut weapon in query.iter_mut() {
+        *weapon = Weapon::new(weapon.kind.next());
+    }
+}
+
+fn tick_fire_cooldowns(mut query: Query<&mut FireCooldown>, time: Res<Time>) {
+    for mut cooldown in query.iter_mut() {
+        if !cooldown.ready() {
+            cooldown.remaining -= time.delta_seconds();
+        }
+    }
+}
+
+fn steer_homing_projectiles(
+    mut projectile_query: Query<(&mut Transform, &mut Velocity, &Homing)>,
+    target_query: Query<&Transform, (With<Asteroid>, Without<Homing>)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    time: Res<Time>,
+) {
+    let window = window_query.get_single().unwrap();
+    let field = play_field_size(window);
+
+    for (mut transform, mut velocity, homing) in projectile_query.iter_mut() {
+        let position = transform.translation.xy();
+        let nearest = target_query.iter()
+            .map(|target| wrapped_offset(position, target.translation.xy(), field))
+            .min_by(|a, b| a.length_squared().total_cmp(&b.length_squared()));
+        let Some(offset) = nearest.filter(|offset| *offset != Vec2::ZERO) else {
+            continue;
+        };
+
+        // Turn towards the target, but no faster than the turn rate allows
+        let heading = velocity.value.xy();
+        let turn = heading.angle_between(offset);
+        let max_turn = homing.turn_rate * time.delta_seconds();
+        let heading = Vec2::from_angle(turn.clamp(-max_turn, max_turn)).rotate(heading);
+
+        velocity.value = heading.extend(0.0);
+        transform.rotation = Quat::from_rotation_arc_2d(Vec2::Y, heading.normalize_or_zero());
+    }
+}